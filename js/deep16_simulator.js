@@ -1,4 +1,6 @@
 // Deep16 Simulator - Complete CPU Execution and State Management with Delay Slot
+// Matches the WASM core except for the features doc/User-man.md lists as JS gaps
+// (DS'/SS'/ES', the current SMV encoding, NMI/INT acceptance, illegal-instruction policy).
 class Deep16Simulator {
     constructor() {
         // CORRECTED: 2 megawords = 2^20 words = 1,048,576 words of 16-bit memory
@@ -92,6 +94,11 @@ class Deep16Simulator {
             0x0000, // LDI 0 -> R0
             0xFF41, // MVS DS, R0
             0xFF42, // MVS SS, R0 (ensure SS=0 so ST with R0 base uses physical 0x0000)
+            0xFC25, // LSI R1, 5
+            0xA203, // ST R1, [R0+3] (NMI_VECTOR)
+            0xA204, // ST R1, [R0+4] (ILLEGAL_VECTOR)
+            0xFC3F, // LSI R1, -1
+            0xA205, // ST R1, [R0+5] (HLT, the default handler)
             0xFC21, // LSI R1, 1
             0xFE01, // SWB R1
             0xA200, // ST R1, [R0+0]
@@ -99,12 +106,7 @@ class Deep16Simulator {
             0xA202, // ST R1, [R0+2]
             0xFE40, // JML R0
             0xFFF0, // NOP (delay slot)
-            0xFFF1, // HLT
-            0xFFF1, // HLT
-            0xFFF1, // HLT
-            0xFFF1, // HLT
-            0xFFF1, // HLT
-            0xFFF1, // HLT
+            0xFFFF, // HLT
         ];
         for (let i = 0; i < rom.length; i++) {
             const addr = base + i;
@@ -229,6 +231,10 @@ class Deep16Simulator {
                             // console.log("SMV instruction");
                             this.executeSMV(instruction);
                             return false;
+                        } else if ((instruction >>> 4) === 0b111111111110) {
+                            // LPSW Rx: Rx = PSW of the current context
+                            this.registers[instruction & 0xF] = this.psw & 0xFFFF;
+                            return false;
                         } else if ((instruction >>> 3) === 0b1111111111110) {
                             // console.log("System instruction");
                             this.executeSystem(instruction);
@@ -692,27 +698,27 @@ class Deep16Simulator {
                 // console.log(`ERD: Extra Register Dual = R${rx}, PSW = 0x${this.psw.toString(16)}`);
                 return false;
                 
-            case 0b1100: // SET - Set PSW flags
-                const setFlags = instruction & 0xF;
-                this.psw |= setFlags;
+            case 0b1100: // SET - Set PSW bit imm
+                const setFlags = 1 << (instruction & 0xF);
+                this.psw = (this.psw | setFlags) & 0xFFFF;
                 // console.log(`SET: PSW flags 0x${setFlags.toString(16)} set, PSW = 0x${this.psw.toString(16)}`);
                 return false;
                 
-            case 0b1101: // CLR - Clear PSW flags
-                const clrFlags = instruction & 0xF;
-                this.psw &= ~clrFlags;
+            case 0b1101: // CLR - Clear PSW bit imm
+                const clrFlags = 1 << (instruction & 0xF);
+                this.psw &= ~clrFlags & 0xFFFF;
                 // console.log(`CLR: PSW flags 0x${clrFlags.toString(16)} cleared, PSW = 0x${this.psw.toString(16)}`);
                 return false;
                 
-            case 0b1110: // SET2 - Set upper PSW bits
-                const set2Flags = (instruction & 0xF) << 4;
-                this.psw |= set2Flags;
+            case 0b1110: // SET2 - Set PSW bit imm+4
+                const set2Flags = 1 << ((instruction & 0xF) + 4);
+                this.psw = (this.psw | set2Flags) & 0xFFFF;
                 // console.log(`SET2: PSW bits 0x${set2Flags.toString(16)} set, PSW = 0x${this.psw.toString(16)}`);
                 return false;
                 
-            case 0b1111: // CLR2 - Clear upper PSW bits
-                const clr2Flags = (instruction & 0xF) << 4;
-                this.psw &= ~clr2Flags;
+            case 0b1111: // CLR2 - Clear PSW bit imm+4
+                const clr2Flags = 1 << ((instruction & 0xF) + 4);
+                this.psw &= ~clr2Flags & 0xFFFF;
                 // console.log(`CLR2: PSW bits 0x${clr2Flags.toString(16)} cleared, PSW = 0x${this.psw.toString(16)}`);
                 return false;
                 
@@ -888,6 +894,9 @@ class Deep16Simulator {
             case 0b000:
                 break;
             case 0b001:
+                // FSH: a pending branch whose delay slot holds FSH is discarded
+                this.delaySlotActive = false;
+                this.branchTaken = false;
                 break;
             case 0b010:
                 this.executeSWI();
@@ -903,7 +912,10 @@ class Deep16Simulator {
      * Execute Software Interrupt with proper context switching
      */
     executeSWI() {
-        this.shadowRegisters.PSW = this.psw;
+        // SWI inside a handler keeps PSW' so RETI still returns to the normal context
+        if (!(this.psw & (1 << 5))) {
+            this.shadowRegisters.PSW = this.psw;
+        }
         this.psw = (this.psw & ~(1 << 4)) | (1 << 5);
         this.shadowRegisters.CS = 0x0000;
         const pa = this.phys(0, 2);
@@ -918,9 +930,13 @@ class Deep16Simulator {
     executeRETI() {
         // console.log("RETI: Return from interrupt - switching to normal context");
         
-        // Simply switch back to normal view (clear S-bit)
-        // No register copying - pure view switching
-        this.psw = this.psw & ~(1 << 5); // Clear S-bit
+        // Switch back to normal view with the pre-interrupt PSW (flags, I, SR/ER)
+        if (this.psw & (1 << 5)) {
+            this.psw = this.shadowRegisters.PSW & ~(1 << 5);
+        }
+        // Flush: a branch whose delay slot holds RETI is discarded
+        this.delaySlotActive = false;
+        this.branchTaken = false;
         
         // console.log(`RETI: Switched to normal context - accessing PC, CS, PSW views`);
         // console.log(`RETI: PSW=0x${this.psw.toString(16)}, PC=0x${this.registers[15].toString(16)}, CS=0x${this.segmentRegisters.CS.toString(16)}`);
@@ -1679,8 +1679,14 @@ class DeepWebUI {
             this.updateAllDisplays();
             if (!cont) {
                 this.simulator.running = false;
-                this.status("Program halted");
-                this.addTranscriptEntry("Program halted after step (WASM)", "info");
+                const fault = this.wasmFaultMessage();
+                if (fault) {
+                    this.status(`Program stopped: ${fault}`);
+                    this.addTranscriptEntry(`Program stopped: ${fault} (WASM)`, "error");
+                } else {
+                    this.status("Program halted");
+                    this.addTranscriptEntry("Program halted after step (WASM)", "info");
+                }
                 this.updateRunButton(false);
             }
             this.addTranscriptEntry(`Step (WASM): 0x${beforePhys.toString(16).padStart(5,'0')} -> 0x${afterPhys.toString(16).padStart(5,'0')}`, "info");
@@ -2006,8 +2012,14 @@ class DeepWebUI {
                     this.addTranscriptEntry("Program execution halted at breakpoint (WASM)", "warning");
                     this.resumeFromBreakpoint = true;
                 } else {
-                    this.status("Program completed");
-                    this.addTranscriptEntry("Program execution completed (WASM)", "success");
+                    const fault = this.wasmFaultMessage();
+                    if (fault) {
+                        this.status(`Program stopped: ${fault}`);
+                        this.addTranscriptEntry(`Program stopped: ${fault} (WASM)`, "error");
+                    } else {
+                        this.status("Program completed");
+                        this.addTranscriptEntry("Program execution completed (WASM)", "success");
+                    }
                 }
                 this.updateRunButton(false);
             }
@@ -2027,6 +2039,19 @@ class DeepWebUI {
         }, 10);
     }
 
+    // Why the WASM core stopped, if it was a fault rather than a plain HLT
+    wasmFaultMessage() {
+        try {
+            if (typeof window.Deep16Wasm.get_stop_reason !== 'function') return null;
+            const reason = window.Deep16Wasm.get_stop_reason();
+            // 0 running, 1 HLT, 6/7 break- and watchpoints: not faults
+            if (!reason || reason[0] <= 1 || reason[0] >= 6) return null;
+            return window.Deep16Wasm.get_stop_message();
+        } catch {
+            return null;
+        }
+    }
+
     status(message) {
         document.getElementById('status-bar').textContent = `DeepCode: ${message}`;
     }
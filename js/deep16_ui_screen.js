@@ -11,6 +11,13 @@ class Deep16ScreenUI {
         this.pendingUpdates = new Set();
         this.flushIntervalMs = 33;
         this.flushTimerId = null;
+        // Colours of the attribute byte's 3-bit fields, as the Rust screen
+        // device defines them: black, blue, green, cyan, red, magenta, brown, white
+        this.palette = ['#000000', '#0000aa', '#00aa00', '#00aaaa', '#aa0000', '#aa00aa', '#aa5500', '#ffffff'];
+        // Whether the grid shows the WASM screen device, so only its dirty cells need redrawing
+        this.wasmScreenSynced = false;
+        this.lastVideoRegisters = null;
+        this.cursorIndex = -1;
         
         this.initializeScreen();
     }
@@ -38,7 +45,10 @@ class Deep16ScreenUI {
         // Update all characters from screen memory
         if (this.ui.useWasm && this.ui.wasmAvailable && this.ui.wasmInitialized && window.Deep16Wasm) {
             try {
-                if (typeof window.Deep16Wasm.get_memory_slice === 'function') {
+                if (typeof window.Deep16Wasm.get_screen_dirty === 'function') {
+                    this.updateFromWasmScreen(window.Deep16Wasm);
+                    return;
+                } else if (typeof window.Deep16Wasm.get_memory_slice === 'function') {
                     const slice = window.Deep16Wasm.get_memory_slice(this.screenBaseAddress, this.totalChars);
                     for (let i = 0; i < slice.length; i++) {
                         const charCode = slice[i] & 0xFF;
@@ -58,6 +68,8 @@ class Deep16ScreenUI {
             }
         }
         {
+            this.wasmScreenSynced = false;
+            this.setCursor(-1);
             for (let i = 0; i < this.totalChars; i++) {
                 const memoryAddress = this.screenBaseAddress + i;
                 if (memoryAddress < this.ui.simulator.memory.length) {
@@ -69,6 +81,54 @@ class Deep16ScreenUI {
         }
     }
 
+    // Redraws the cells the WASM screen device reports as changed, or every
+    // cell when the grid last showed something else or the video registers
+    // that colour all cells have changed
+    updateFromWasmScreen(W) {
+        const video = W.get_video_registers();
+        const last = this.lastVideoRegisters;
+        const full = !this.wasmScreenSynced || !last || last[0] !== video[0] || last[3] !== video[3];
+        const spans = W.get_screen_dirty();
+        if (full) {
+            const cells = W.get_screen_cells(0, this.totalChars);
+            for (let i = 0; i < cells.length; i++) this.updateCell(i, cells[i], video);
+        } else {
+            for (let i = 0; i + 2 < spans.length; i += 3) {
+                const start = spans[i] * this.screenWidth + spans[i + 1];
+                const cells = W.get_screen_cells(start, spans[i + 2] - spans[i + 1]);
+                for (let j = 0; j < cells.length; j++) this.updateCell(start + j, cells[j], video);
+            }
+        }
+        this.wasmScreenSynced = true;
+        this.lastVideoRegisters = video;
+        const cursorOn = (video[0] & 1) !== 0;
+        this.setCursor(cursorOn ? video[2] * this.screenWidth + video[1] : -1);
+    }
+
+    // Draws a cell word: character in the low byte, attributes in the high
+    // byte (0 means the video controller's default attributes)
+    updateCell(charIndex, word, video) {
+        this.updateCharacter(charIndex, word & 0xFF);
+        const charElement = document.getElementById(`screen-char-${charIndex}`);
+        if (!charElement) return;
+        const attr = (word >> 8) || (video[3] & 0xFF);
+        let fg = attr & 7;
+        let bg = (attr >> 3) & 7;
+        if (attr & 0x40) [fg, bg] = [bg, fg];
+        charElement.style.color = this.palette[fg];
+        charElement.style.backgroundColor = this.palette[bg];
+        charElement.classList.toggle('screen-blink', (attr & 0x80) !== 0 && (video[0] & 2) !== 0);
+    }
+
+    setCursor(charIndex) {
+        if (charIndex === this.cursorIndex) return;
+        const old = document.getElementById(`screen-char-${this.cursorIndex}`);
+        if (old) old.classList.remove('screen-cursor');
+        const now = document.getElementById(`screen-char-${charIndex}`);
+        if (now) now.classList.add('screen-cursor');
+        this.cursorIndex = charIndex;
+    }
+
     updateCharacter(charIndex, charCode) {
         const charElement = document.getElementById(`screen-char-${charIndex}`);
         if (!charElement) return;
@@ -131,9 +191,16 @@ class Deep16ScreenUI {
         return address >= this.screenBaseAddress && address <= this.screenEndAddress;
     }
 
+    // The WASM core's screen device tracks its own changes, which
+    // updateScreenDisplay picks up after each run batch
+    usesWasmScreen() {
+        return this.ui.useWasm && this.ui.wasmAvailable && this.ui.wasmInitialized && window.Deep16Wasm &&
+            typeof window.Deep16Wasm.get_screen_dirty === 'function';
+    }
+
     // Handle memory writes to screen area
     handleScreenMemoryWrite(address, value) {
-        if (this.isScreenMemory(address)) {
+        if (this.isScreenMemory(address) && !this.usesWasmScreen()) {
             const charIndex = address - this.screenBaseAddress;
             if (this.deferUpdates) {
                 this.pendingUpdates.add(charIndex);
@@ -0,0 +1,145 @@
+//! Memory-mapped devices (spec section 10.1).
+//!
+//! Every physical address a device has not claimed is plain RAM. A device
+//! mapped over a range sees the CPU's instruction fetches and data accesses
+//! there as `read` and `write` calls with the offset into its range, so reads
+//! can have side effects (popping a FIFO, acknowledging an interrupt) and
+//! writes need not read back. Debugger views use the side-effect-free `peek`.
+//! Devices are clocked once per executed step, or by the step's cycles with
+//! timing on, and may drive the interrupt line.
+
+use std::any::Any;
+
+use crate::cpu::Cpu;
+
+/// A peripheral occupying a range of physical addresses.
+pub trait Device: Any {
+    /// Data read of the word at `offset` from the start of the range.
+    fn read(&mut self, offset: usize) -> u16;
+
+    /// The value `read` would return, without its side effects.
+    fn peek(&self, offset: usize) -> u16;
+
+    fn write(&mut self, offset: usize, value: u16);
+
+    /// Advances the device by `cycles` clock cycles.
+    fn tick(&mut self, _cycles: u64) {}
+
+    /// Whether the device is requesting an interrupt. The CPU interrupt line
+    /// is high while any device or `Cpu::set_irq_line` holds it high.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Returns to the power-on state; called by `Cpu::reset`.
+    fn reset(&mut self) {}
+
+    /// State to keep in a machine snapshot, in a layout of the device's
+    /// choosing; empty if there is nothing worth keeping.
+    fn save(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Restores state written by `save`, starting from the reset state and
+    /// taking whatever fields `data` has, so layouts can grow. Devices a
+    /// snapshot holds nothing for are loaded with empty data.
+    fn load(&mut self, data: &[u8]) {
+        let _ = data;
+        self.reset();
+    }
+}
+
+struct Mapping {
+    start: usize,
+    end: usize,
+    device: Box<dyn Device>,
+}
+
+#[derive(Default)]
+pub(crate) struct Bus {
+    /// Sorted by address and never overlapping.
+    maps: Vec<Mapping>,
+}
+
+impl Bus {
+    fn find(&self, addr: usize) -> Option<&Mapping> {
+        let m = self.maps.first()?;
+        if addr < m.start { return None; }
+        self.maps.iter().find(|m| (m.start..=m.end).contains(&addr))
+    }
+
+    fn find_mut(&mut self, addr: usize) -> Option<&mut Mapping> {
+        let m = self.maps.first()?;
+        if addr < m.start { return None; }
+        self.maps.iter_mut().find(|m| (m.start..=m.end).contains(&addr))
+    }
+
+    pub(crate) fn is_mapped(&self, addr: usize) -> bool {
+        self.find(addr).is_some()
+    }
+
+    /// `None` if no device claims `addr`.
+    pub(crate) fn read(&mut self, addr: usize) -> Option<u16> {
+        self.find_mut(addr).map(|m| m.device.read(addr - m.start))
+    }
+
+    pub(crate) fn peek(&self, addr: usize) -> Option<u16> {
+        self.find(addr).map(|m| m.device.peek(addr - m.start))
+    }
+
+    /// Returns `false` if no device claims `addr`.
+    pub(crate) fn write(&mut self, addr: usize, value: u16) -> bool {
+        let Some(m) = self.find_mut(addr) else { return false; };
+        m.device.write(addr - m.start, value);
+        true
+    }
+
+    pub(crate) fn irq(&self) -> bool {
+        self.maps.iter().any(|m| m.device.irq())
+    }
+
+    pub(crate) fn reset(&mut self) {
+        for m in &mut self.maps { m.device.reset(); }
+    }
+
+    /// `(start, state)` of every device with state to save.
+    pub(crate) fn save(&self) -> Vec<(usize, Vec<u8>)> {
+        self.maps.iter().map(|m| (m.start, m.device.save())).filter(|(_, s)| !s.is_empty()).collect()
+    }
+
+    /// Loads each device with the state saved for its start address.
+    pub(crate) fn load(&mut self, states: &[(usize, &[u8])]) {
+        for m in &mut self.maps {
+            let state = states.iter().find(|(start, _)| *start == m.start).map_or(&[][..], |s| s.1);
+            m.device.load(state);
+        }
+    }
+
+    pub(crate) fn map(&mut self, start: usize, len: usize, device: Box<dyn Device>) -> bool {
+        let Some(end) = len.checked_sub(1).and_then(|l| start.checked_add(l)) else { return false; };
+        if self.maps.iter().any(|m| start <= m.end && m.start <= end) { return false; }
+        let at = self.maps.partition_point(|m| m.start < start);
+        self.maps.insert(at, Mapping { start, end, device });
+        true
+    }
+
+    pub(crate) fn unmap(&mut self, start: usize) -> Option<Box<dyn Device>> {
+        let i = self.maps.iter().position(|m| m.start == start)?;
+        Some(self.maps.remove(i).device)
+    }
+
+    pub(crate) fn device<D: Device>(&self) -> Option<&D> {
+        self.maps.iter().find_map(|m| (m.device.as_ref() as &dyn Any).downcast_ref())
+    }
+
+    pub(crate) fn device_mut<D: Device>(&mut self) -> Option<&mut D> {
+        self.maps.iter_mut().find_map(|m| (m.device.as_mut() as &mut dyn Any).downcast_mut())
+    }
+}
+
+/// Clocks every device for the step that just ran.
+pub(crate) fn tick(c: &mut Cpu) {
+    if c.bus.maps.is_empty() { return; }
+    let cycles = if c.timing.enabled { c.timing.counters.last_step_cycles } else { 1 };
+    for m in &mut c.bus.maps { m.device.tick(cycles); }
+}
@@ -0,0 +1,756 @@
+use std::fmt;
+
+use crate::bus::{Bus, Device};
+use crate::debug::{BreakAt, Debugger, WatchHit, WatchKind};
+use crate::exec::step_one;
+use crate::expr::{self, ExprError};
+use crate::history::{self, History};
+use crate::keyboard::{Keyboard, KEYBOARD_BASE, KEYBOARD_LEN};
+use crate::pipeline::{self, CycleCounters, StageSlot, Timing};
+use crate::screen::{DirtySpan, Screen, VideoController, SCREEN_BASE, SCREEN_LEN, VIDEO_BASE, VIDEO_LEN};
+use crate::snapshot::{self, SnapshotError};
+use crate::timer::{Timer, TIMER_BASE, TIMER_LEN};
+use crate::trace::{Trace, TraceEntry, TraceFilter};
+use crate::uart::{Uart, UART_BASE, UART_LEN};
+
+/// Interrupt vector table in segment 0 (spec section 5.1).
+pub const RESET_VECTOR: u16 = 0x0000;
+pub const HW_INT_VECTOR: u16 = 0x0001;
+pub const SWI_VECTOR: u16 = 0x0002;
+pub const NMI_VECTOR: u16 = 0x0003;
+pub const ILLEGAL_VECTOR: u16 = 0x0004;
+
+/// `last_event_code` values for steps that did not fetch an instruction of
+/// their own, or that changed context.
+pub const EVENT_IRQ: u16 = 1;
+pub const EVENT_SWI: u16 = 2;
+pub const EVENT_RETI: u16 = 3;
+pub const EVENT_NMI: u16 = 4;
+pub const EVENT_TRAP: u16 = 5;
+
+/// Complete architectural state of one Deep16 processor plus its memory.
+pub struct Cpu {
+    pub(crate) mem: Vec<u16>,
+    /// One bit per memory word, set once the word has been written.
+    pub(crate) written: Vec<u64>,
+    pub(crate) reg: [u16; 16],
+    pub(crate) psw: u16,
+    pub(crate) spsw: u16,
+    pub(crate) cs: u16,
+    pub(crate) scs: u16,
+    pub(crate) spc: u16,
+    pub(crate) ds: u16,
+    pub(crate) ss: u16,
+    pub(crate) es: u16,
+    pub(crate) sds: u16,
+    pub(crate) sss: u16,
+    pub(crate) ses: u16,
+    pub(crate) running: bool,
+    pub(crate) stop_reason: Option<StopReason>,
+    pub(crate) illegal_policy: IllegalPolicy,
+    pub(crate) timing: Timing,
+    pub(crate) debug: Debugger,
+    pub(crate) history: History,
+    pub(crate) trace: Trace,
+    pub(crate) bus: Bus,
+    /// Physical address, CS:PC and word of the instruction being executed.
+    pub(crate) fetch_addr: usize,
+    pub(crate) fetch_cs: u16,
+    pub(crate) fetch_pc: u16,
+    pub(crate) fetch_instr: u16,
+    pub(crate) delay_active: bool,
+    pub(crate) delayed_pc: u16,
+    pub(crate) delayed_cs: u16,
+    pub(crate) delayed_to_shadow: bool,
+    pub(crate) branch_taken: bool,
+    pub(crate) last_alu_result: i32,
+    pub(crate) last_op_alu: bool,
+    pub(crate) recent_addr: usize,
+    pub(crate) recent_base: u16,
+    pub(crate) recent_offset: u16,
+    pub(crate) recent_seg_val: u16,
+    pub(crate) recent_seg_idx: u16,
+    pub(crate) recent_is_store: bool,
+    pub(crate) last_event_code: u16,
+    pub(crate) last_event_spc: u16,
+    pub(crate) last_event_scs: u16,
+    pub(crate) irq_pending: bool,
+    pub(crate) irq_line: bool,
+    pub(crate) nmi_pending: bool,
+    pub(crate) nmi_active: bool,
+}
+
+impl Cpu {
+    /// Creates a machine with `mem_words` words of memory in its power-on state,
+    /// with the boot ROM loaded at 0xFFFF0, the interval timer mapped at
+    /// `TIMER_BASE`, the video controller at `VIDEO_BASE`, the serial port at
+    /// `UART_BASE`, the keyboard controller at `KEYBOARD_BASE` and the screen
+    /// buffer at `SCREEN_BASE`.
+    pub fn new(mem_words: usize) -> Cpu {
+        let mut reg = [0u16; 16];
+        reg[13] = 0x7FFF;
+        reg[15] = 0x0000;
+        let mut c = Cpu {
+            mem: vec![0xFFFF; mem_words],
+            written: vec![0; mem_words.div_ceil(64)],
+            reg,
+            psw: 0,
+            spsw: 0,
+            cs: 0xFFFF,
+            scs: 0,
+            spc: 0,
+            ds: 0x1000,
+            ss: 0x8000,
+            es: 0x2000,
+            sds: 0,
+            sss: 0,
+            ses: 0,
+            running: false,
+            stop_reason: None,
+            illegal_policy: IllegalPolicy::default(),
+            timing: Timing::default(),
+            debug: Debugger::default(),
+            history: History::default(),
+            trace: Trace::default(),
+            bus: Bus::default(),
+            fetch_addr: 0,
+            fetch_cs: 0,
+            fetch_pc: 0,
+            fetch_instr: 0,
+            delay_active: false,
+            delayed_pc: 0,
+            delayed_cs: 0,
+            delayed_to_shadow: false,
+            branch_taken: false,
+            last_alu_result: 0,
+            last_op_alu: false,
+            recent_addr: 0,
+            recent_base: 0,
+            recent_offset: 0,
+            recent_seg_val: 0,
+            recent_seg_idx: 0,
+            recent_is_store: false,
+            last_event_code: 0,
+            last_event_spc: 0,
+            last_event_scs: 0,
+            irq_pending: false,
+            irq_line: false,
+            nmi_pending: false,
+            nmi_active: false,
+        };
+        c.bus.map(TIMER_BASE, TIMER_LEN, Box::new(Timer::default()));
+        c.bus.map(VIDEO_BASE, VIDEO_LEN, Box::new(VideoController::default()));
+        c.bus.map(UART_BASE, UART_LEN, Box::new(Uart::default()));
+        c.bus.map(KEYBOARD_BASE, KEYBOARD_LEN, Box::new(Keyboard::default()));
+        c.bus.map(SCREEN_BASE, SCREEN_LEN, Box::new(Screen::default()));
+        autoload_rom(&mut c);
+        c
+    }
+
+    /// Returns the machine to its power-on state, clearing memory, resetting
+    /// the mapped devices and reloading the boot ROM. Breakpoints, watchpoints,
+    /// the illegal-instruction policy, the timing mode, the history limit, the
+    /// trace settings and the device map are kept; the cycle counters, undo
+    /// history and trace start again from zero.
+    pub fn reset(&mut self) {
+        self.mem.fill(0xFFFF);
+        self.written.fill(0);
+        self.reg = [0u16; 16];
+        self.reg[13] = 0x7FFF;
+        self.reg[15] = 0x0000;
+        self.psw = 0;
+        self.spsw = 0;
+        self.cs = 0xFFFF;
+        self.scs = 0;
+        self.spc = 0;
+        self.ds = 0x1000;
+        self.ss = 0x8000;
+        self.es = 0x2000;
+        self.sds = 0;
+        self.sss = 0;
+        self.ses = 0;
+        self.running = false;
+        self.stop_reason = None;
+        self.timing = self.timing.cleared();
+        self.debug.resume_addr = None;
+        self.debug.watch_hit = None;
+        self.history.clear();
+        self.trace.clear();
+        self.fetch_addr = 0;
+        self.fetch_cs = 0;
+        self.fetch_pc = 0;
+        self.fetch_instr = 0;
+        self.delay_active = false;
+        self.delayed_pc = 0;
+        self.delayed_cs = 0;
+        self.delayed_to_shadow = false;
+        self.branch_taken = false;
+        self.last_alu_result = 0;
+        self.last_op_alu = false;
+        self.recent_addr = 0;
+        self.recent_base = 0;
+        self.recent_offset = 0;
+        self.recent_seg_val = 0;
+        self.recent_seg_idx = 0;
+        self.recent_is_store = false;
+        self.last_event_code = 0;
+        self.last_event_spc = 0;
+        self.last_event_scs = 0;
+        self.irq_pending = false;
+        self.irq_line = false;
+        self.nmi_pending = false;
+        self.nmi_active = false;
+        self.bus.reset();
+        autoload_rom(self);
+    }
+
+    /// Executes one instruction, ignoring breakpoints. Returns `false` once the
+    /// machine has stopped; `stop_reason` then says why.
+    pub fn step(&mut self) -> bool {
+        step_one(self)
+    }
+
+    /// Executes up to `max_steps` instructions, stopping early if the machine
+    /// halts, is about to execute an instruction with a breakpoint (including
+    /// one in a delay slot), or has just made a watched data access. Returns
+    /// `false` if it stopped before the budget was used up. Running again
+    /// after a breakpoint executes that instruction.
+    pub fn run(&mut self, max_steps: u32) -> bool {
+        self.debug.armed = true;
+        let mut ok = true;
+        for _ in 0..max_steps {
+            if !step_one(self) { ok = false; break; }
+        }
+        self.debug.armed = false;
+        ok
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Why the machine last stopped, or `None` while it is running (or has
+    /// not been started since the last reset).
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    pub fn illegal_policy(&self) -> IllegalPolicy {
+        self.illegal_policy
+    }
+
+    /// Chooses what happens when an undefined or reserved encoding is executed.
+    pub fn set_illegal_policy(&mut self, policy: IllegalPolicy) {
+        self.illegal_policy = policy;
+    }
+
+    /// Turns the pipeline timing model on or off. Execution is the same either
+    /// way; with timing on, every step also updates the cycle counters.
+    pub fn set_timing(&mut self, enabled: bool) {
+        self.timing.enabled = enabled;
+    }
+
+    pub fn timing_enabled(&self) -> bool {
+        self.timing.enabled
+    }
+
+    pub fn cycle_counters(&self) -> CycleCounters {
+        self.timing.counters
+    }
+
+    pub fn reset_cycle_counters(&mut self) {
+        self.timing = self.timing.cleared();
+    }
+
+    /// Advances one clock of the pipeline view (spec section 7), executing
+    /// the instruction that enters IF. Turns timing on. Returns `false` once
+    /// the machine has stopped and the last instruction has left WB. Mixing
+    /// this with `step` leaves the stage view showing only the instructions
+    /// that were cycle-stepped.
+    pub fn step_cycle(&mut self) -> bool {
+        pipeline::step_cycle(self)
+    }
+
+    /// Stage occupancy `[IF, ID, EX, MEM, WB]` after the last `step_cycle`.
+    pub fn pipeline_stages(&self) -> [StageSlot; 5] {
+        self.timing.view.stages
+    }
+
+    /// Serializes memory, registers, segments, shadow state, PSW, the branch
+    /// delay latches, interrupt state and device state in the versioned
+    /// snapshot format (doc/Deep16-Snapshot.md). Debugger settings and timing
+    /// are not saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        snapshot::save(self)
+    }
+
+    /// Restores a snapshot from `save_state` of a machine with the same memory
+    /// size. The machine is left stopped, with cleared cycle counters and undo
+    /// history. On error nothing changes.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SnapshotError> {
+        snapshot::load(self, data)
+    }
+
+    /// Undoes the last `n` steps recorded in the history (instructions,
+    /// delay slots and interrupt entries) and returns how many were undone.
+    /// Memory, registers, PSW, segments, the shadow context and any pending
+    /// branch come back exactly; cycle counters and device state do not.
+    pub fn step_back(&mut self, n: u32) -> u32 {
+        history::step_back(self, n)
+    }
+
+    /// Keeps at most `limit` steps of undo history; 0 turns it off.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.set_limit(limit);
+    }
+
+    pub fn history_limit(&self) -> usize {
+        self.history.limit
+    }
+
+    /// Number of steps `step_back` can currently undo.
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// Records up to `capacity` executed instructions in the trace buffer,
+    /// dropping the oldest beyond that; 0 turns tracing off.
+    pub fn set_trace(&mut self, capacity: usize) {
+        self.trace.set_capacity(capacity);
+    }
+
+    pub fn trace_capacity(&self) -> usize {
+        self.trace.capacity
+    }
+
+    pub fn set_trace_filter(&mut self, filter: TraceFilter) {
+        self.trace.filter = filter;
+    }
+
+    pub fn trace_filter(&self) -> TraceFilter {
+        self.trace.filter
+    }
+
+    pub fn trace_len(&self) -> usize {
+        self.trace.entries.len()
+    }
+
+    /// Removes and returns the recorded entries, oldest first.
+    pub fn take_trace(&mut self) -> Vec<TraceEntry> {
+        self.trace.entries.drain(..).collect()
+    }
+
+    /// Sets an execution breakpoint and returns its id.
+    pub fn add_breakpoint(&mut self, at: BreakAt) -> u32 {
+        self.debug.add(at)
+    }
+
+    /// Sets an execution breakpoint that only stops when `condition` (see the
+    /// `expr` syntax, e.g. `R11 == 0x0234 && Z`) is non-zero and after
+    /// `ignore` such hits have gone by. Returns the id, or the parse error.
+    pub fn add_conditional_breakpoint(&mut self, at: BreakAt, condition: &str, ignore: u32) -> Result<u32, ExprError> {
+        let cond = expr::parse_condition(condition)?;
+        let id = self.debug.add(at);
+        let bp = self.debug.breakpoint_mut(id).expect("just added");
+        bp.condition = cond;
+        bp.ignore = ignore;
+        Ok(id)
+    }
+
+    /// Replaces the condition of breakpoint `id`; an empty string makes it
+    /// unconditional. On a parse error the old condition is kept.
+    pub fn set_breakpoint_condition(&mut self, id: u32, condition: &str) -> Result<(), ExprError> {
+        let cond = expr::parse_condition(condition)?;
+        let Some(bp) = self.debug.breakpoint_mut(id) else {
+            return Err(ExprError { pos: 0, msg: format!("no breakpoint {id}") });
+        };
+        bp.condition = cond;
+        Ok(())
+    }
+
+    /// Sets how many hits of breakpoint `id` to let pass and restarts its hit
+    /// count; returns `false` if there is no such breakpoint.
+    pub fn set_breakpoint_ignore_count(&mut self, id: u32, ignore: u32) -> bool {
+        let Some(bp) = self.debug.breakpoint_mut(id) else { return false; };
+        bp.ignore = ignore;
+        bp.hits = 0;
+        true
+    }
+
+    /// How often breakpoint `id` was reached with its condition true,
+    /// including ignored hits.
+    pub fn breakpoint_hits(&self, id: u32) -> Option<u32> {
+        self.debug.breakpoints.iter().find(|b| b.id == id).map(|b| b.hits)
+    }
+
+    /// Removes breakpoint `id`; returns `false` if there was none.
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.debug.remove(id)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debug.breakpoints.clear();
+    }
+
+    /// Watches LD/ST/LDS/STS accesses to physical addresses `start..=end` and
+    /// returns the watchpoint id. `run` stops after the accessing instruction.
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, kind: WatchKind) -> u32 {
+        self.debug.add_watch(start, end, kind)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+        self.debug.remove_watch(id)
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.debug.watchpoints.clear();
+    }
+
+    /// The access behind the most recent watchpoint stop.
+    pub fn watch_hit(&self) -> Option<WatchHit> {
+        self.debug.watch_hit
+    }
+
+    /// Latches a hardware interrupt request (edge triggered). It is taken at
+    /// the next instruction boundary with PSW.I=1 outside the shadow view and
+    /// outside a branch delay slot, then cleared.
+    pub fn raise_interrupt(&mut self) {
+        self.irq_pending = true;
+    }
+
+    /// Drives the level-triggered interrupt line. While high, an interrupt is
+    /// taken at every eligible instruction boundary, so a device must drop the
+    /// line once it has been serviced. Mapped devices drive the same line
+    /// through `Device::irq`.
+    pub fn set_irq_line(&mut self, level: bool) {
+        self.irq_line = level;
+    }
+
+    /// Latches a non-maskable interrupt. It ignores PSW.I and is taken at the
+    /// next instruction boundary outside a delay slot. If it arrives while a
+    /// handler runs in the shadow view, that handler is abandoned and the NMI
+    /// handler restarts the shadow context, returning to the interrupted
+    /// normal context. A second NMI waits until the first NMI handler's RETI.
+    pub fn raise_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    /// Maps `device` over the `len` words from physical address `start`, in
+    /// front of the RAM there. Returns `false` (and maps nothing) if `len` is
+    /// 0 or the range overlaps a mapped device.
+    pub fn map_device<D: Device>(&mut self, start: usize, len: usize, device: D) -> bool {
+        self.bus.map(start, len, Box::new(device))
+    }
+
+    /// Removes the device mapped at `start`, uncovering the RAM behind it.
+    pub fn unmap_device(&mut self, start: usize) -> Option<Box<dyn Device>> {
+        self.bus.unmap(start)
+    }
+
+    /// The first mapped device of type `D`.
+    pub fn device<D: Device>(&self) -> Option<&D> {
+        self.bus.device()
+    }
+
+    pub fn device_mut<D: Device>(&mut self) -> Option<&mut D> {
+        self.bus.device_mut()
+    }
+
+    /// Queues a key code in the keyboard controller; returns `false` if there
+    /// is none mapped or its FIFO is full.
+    pub fn push_key(&mut self, code: u16) -> bool {
+        self.device_mut::<Keyboard>().is_some_and(|k| k.push_key(code))
+    }
+
+    /// Queues bytes for the program to receive on the serial port.
+    pub fn uart_write_input(&mut self, bytes: &[u8]) {
+        if let Some(u) = self.device_mut::<Uart>() { u.write_input(bytes); }
+    }
+
+    /// Removes and returns the bytes the program has sent on the serial port.
+    pub fn uart_read_output(&mut self) -> Vec<u8> {
+        self.device_mut::<Uart>().map(Uart::read_output).unwrap_or_default()
+    }
+
+    /// Passes each byte the program sends on the serial port to `f` instead
+    /// of collecting it for `uart_read_output`.
+    pub fn set_uart_output_callback(&mut self, f: impl FnMut(u8) + 'static) {
+        if let Some(u) = self.device_mut::<Uart>() { u.set_output_callback(Some(Box::new(f))); }
+    }
+
+    /// The text screen as one line per row; empty if no screen is mapped.
+    pub fn screen_text(&self) -> String {
+        self.device::<Screen>().map(Screen::text).unwrap_or_default()
+    }
+
+    /// Screen cells changed since the last call, one span per row.
+    pub fn take_screen_dirty(&mut self) -> Vec<DirtySpan> {
+        self.device_mut::<Screen>().map(Screen::take_dirty).unwrap_or_default()
+    }
+
+    /// Copies `data` into memory at physical word address `addr`, points
+    /// execution back at the boot ROM and forgets the undo history. Returns
+    /// `false` (and loads nothing) if the data does not fit.
+    pub fn load_program(&mut self, addr: usize, data: &[u16]) -> bool {
+        let Some(end) = addr.checked_add(data.len()).filter(|&e| e <= self.mem.len()) else {
+            return false;
+        };
+        self.mem[addr..end].copy_from_slice(data);
+        for a in addr..end { self.mark_written(a); }
+        self.history.clear();
+        self.reg[15] = 0;
+        self.cs = 0xFFFF;
+        true
+    }
+
+    pub(crate) fn mark_written(&mut self, addr: usize) {
+        self.written[addr / 64] |= 1 << (addr % 64);
+    }
+
+    /// Whether anything (a program load, a store or the boot ROM) has written
+    /// the word at `addr` since the last reset.
+    pub(crate) fn is_written(&self, addr: usize) -> bool {
+        self.written.get(addr / 64).is_some_and(|w| (w >> (addr % 64)) & 1 != 0)
+    }
+
+    pub(crate) fn in_shadow(&self) -> bool {
+        (self.psw & (1 << 5)) != 0
+    }
+
+    /// Segment register `idx` (0=CS, 1=DS, 2=SS, 3=ES) of the active context.
+    pub(crate) fn seg(&self, idx: u16) -> u16 {
+        match (idx & 3, self.in_shadow()) {
+            (0, false) => self.cs,
+            (1, false) => self.ds,
+            (2, false) => self.ss,
+            (3, false) => self.es,
+            (0, true) => self.scs,
+            (1, true) => self.sds,
+            (2, true) => self.sss,
+            _ => self.ses,
+        }
+    }
+
+    pub(crate) fn seg_mut(&mut self, idx: u16) -> &mut u16 {
+        match (idx & 3, self.in_shadow()) {
+            (0, false) => &mut self.cs,
+            (1, false) => &mut self.ds,
+            (2, false) => &mut self.ss,
+            (3, false) => &mut self.es,
+            (0, true) => &mut self.scs,
+            (1, true) => &mut self.sds,
+            (2, true) => &mut self.sss,
+            _ => &mut self.ses,
+        }
+    }
+
+    /// General purpose registers as seen by the active context; R15 is PC'
+    /// while the shadow view is active.
+    pub fn registers(&self) -> [u16; 16] {
+        let mut v = self.reg;
+        if self.in_shadow() { v[15] = self.spc; }
+        v
+    }
+
+    /// Writes a register of the active context, so R15 is PC' while the
+    /// shadow view is active, as in `registers`.
+    pub fn set_register(&mut self, idx: usize, value: u16) {
+        match idx & 0xF {
+            15 if self.in_shadow() => self.spc = value,
+            i => self.reg[i] = value,
+        }
+    }
+
+    pub fn psw(&self) -> u16 {
+        self.psw
+    }
+
+    pub fn set_psw(&mut self, value: u16) {
+        self.psw = value;
+    }
+
+    /// Segment registers `[CS, DS, SS, ES]` of the active context.
+    pub fn segments(&self) -> [u16; 4] {
+        [self.seg(0), self.seg(1), self.seg(2), self.seg(3)]
+    }
+
+    /// Loads the segment registers of the active context.
+    pub fn set_segments(&mut self, cs: u16, ds: u16, ss: u16, es: u16) {
+        *self.seg_mut(0) = cs;
+        *self.seg_mut(1) = ds;
+        *self.seg_mut(2) = ss;
+        *self.seg_mut(3) = es;
+    }
+
+    /// Shadow state `[PC', CS', PSW', DS', SS', ES']`.
+    pub fn shadow_state(&self) -> [u16; 6] {
+        [self.spc, self.scs, self.spsw, self.sds, self.sss, self.ses]
+    }
+
+    /// The normal context's `[PC, CS, DS, SS, ES]`, whichever view is active.
+    /// Its PSW is `psw()` in the normal view and PSW' in the shadow view.
+    pub fn normal_state(&self) -> [u16; 5] {
+        [self.reg[15], self.cs, self.ds, self.ss, self.es]
+    }
+
+    /// RAM contents. Words under a mapped device hold the RAM behind it, not
+    /// the device registers; `read_words` gives the view a program sees.
+    pub fn memory(&self) -> &[u16] {
+        &self.mem
+    }
+
+    /// `len` physical words from `start`, read like `read_word`: mapped
+    /// devices are peeked, so this is what a program would load.
+    pub fn read_words(&self, start: usize, len: usize) -> Vec<u16> {
+        (start..start.saturating_add(len)).map(|a| self.read_word(a)).collect()
+    }
+
+    /// Reads a physical word without side effects: a mapped device is
+    /// peeked, and addresses past the end of memory read as 0xFFFF.
+    pub fn read_word(&self, addr: usize) -> u16 {
+        if let Some(v) = self.bus.peek(addr) { return v; }
+        self.mem.get(addr).copied().unwrap_or(0xFFFF)
+    }
+
+    /// Writes a physical word, to a mapped device if there is one; addresses
+    /// past the end of memory are otherwise ignored.
+    pub fn write_word(&mut self, addr: usize, value: u16) {
+        if self.bus.write(addr, value) { return; }
+        if let Some(w) = self.mem.get_mut(addr) {
+            *w = value;
+            self.mark_written(addr);
+        }
+    }
+
+    /// The most recent data memory access (LD/ST/LDS/STS).
+    pub fn recent_access(&self) -> MemAccess {
+        MemAccess {
+            addr: self.recent_addr,
+            base: self.recent_base,
+            offset: self.recent_offset,
+            seg_val: self.recent_seg_val,
+            seg_idx: self.recent_seg_idx,
+            is_store: self.recent_is_store,
+        }
+    }
+
+    /// The last instruction fetched, or one of the `EVENT_*` codes if that
+    /// step entered an interrupt (IRQ, SWI, NMI, illegal-instruction trap) or
+    /// executed RETI. The codes share their values with LDI #1 to #5.
+    pub fn last_event_code(&self) -> u16 {
+        self.last_event_code
+    }
+}
+
+/// What the core does with an undefined or reserved encoding: reserved SOP
+/// types, SMV selectors 0110-1111, SYS ops 100-111 and the unassigned
+/// 0xFF80-0xFFBF and 0xFFF8-0xFFFE ranges.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IllegalPolicy {
+    /// Execute it as a NOP.
+    #[default]
+    Ignore,
+    /// Stop with `StopReason::IllegalInstruction`.
+    Halt,
+    /// Enter the interrupt handler at `ILLEGAL_VECTOR`, like SWI. PC in the
+    /// interrupted context points past the offending word.
+    Trap,
+}
+
+/// Why execution stopped. Faults leave PC just past the faulting
+/// instruction, except in a branch delay slot: there PC stays on the slot
+/// and the branch is still pending, so resuming retries the slot and then
+/// branches. `addr` always names the physical address involved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// HLT (0xFFFF) was executed.
+    Halted,
+    /// An instruction was fetched from a word nothing has written, i.e.
+    /// execution ran off the end of the program.
+    FetchFromUninitialized { addr: usize },
+    /// An instruction fetch or data access went past the end of memory.
+    PhysicalAddressOutOfRange { addr: usize },
+    /// `instr` at `addr` is not a defined instruction.
+    IllegalInstruction { addr: usize, instr: u16 },
+    /// MUL32, DIV32 or JML at `addr` named an odd register as a pair base.
+    OddRegisterPairViolation { addr: usize, instr: u16 },
+    /// `run` reached breakpoint `id`; the instruction at `addr` has not run.
+    Breakpoint { id: u32, addr: usize },
+    /// Watchpoint `id` saw an access to data address `addr`; see `watch_hit`.
+    Watchpoint { id: u32, addr: usize },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::FetchFromUninitialized { addr } => {
+                write!(f, "fetch from uninitialized memory at 0x{addr:05X}")
+            }
+            StopReason::PhysicalAddressOutOfRange { addr } => {
+                write!(f, "physical address 0x{addr:05X} out of range")
+            }
+            StopReason::IllegalInstruction { addr, instr } => {
+                write!(f, "illegal instruction 0x{instr:04X} at 0x{addr:05X}")
+            }
+            StopReason::OddRegisterPairViolation { addr, instr } => {
+                write!(f, "odd register pair in 0x{instr:04X} at 0x{addr:05X}")
+            }
+            StopReason::Breakpoint { id, addr } => write!(f, "breakpoint {id} at 0x{addr:05X}"),
+            StopReason::Watchpoint { id, addr } => write!(f, "watchpoint {id} on 0x{addr:05X}"),
+        }
+    }
+}
+
+/// Details of a data memory access, as recorded by the load/store units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemAccess {
+    /// Physical word address.
+    pub addr: usize,
+    /// Value of the base register.
+    pub base: u16,
+    /// Offset added to the base register (LD/ST only).
+    pub offset: u16,
+    /// Value of the segment register used.
+    pub seg_val: u16,
+    /// Segment register index: 0=CS, 1=DS, 2=SS, 3=ES.
+    pub seg_idx: u16,
+    pub is_store: bool,
+}
+
+pub(crate) fn phys(seg: u16, off: u32) -> usize {
+    (((seg as u32) << 4) + off) as usize
+}
+
+fn autoload_rom(c: &mut Cpu) {
+    let base = 0xFFFF0usize;
+    let rom: [u16; 16] = [
+        0x0000, // LDI 0 -> R0
+        0xFF41, // MVS DS, R0
+        0xFF42, // MVS SS, R0
+        0xFC25, // LSI R1, 5
+        0xA203, // ST R1, [R0+3] (NMI_VECTOR)
+        0xA204, // ST R1, [R0+4] (ILLEGAL_VECTOR)
+        0xFC3F, // LSI R1, -1
+        0xA205, // ST R1, [R0+5] (HLT, the default handler)
+        0xFC21, // LSI R1, 1
+        0xFE01, // SWB R1
+        0xA200, // ST R1, [R0+0]
+        0xA201, // ST R1, [R0+1]
+        0xA202, // ST R1, [R0+2]
+        0xFE40, // JML R0
+        0xFFF0, // NOP (delay slot)
+        0xFFFF, // HLT
+    ];
+    for (i, &w) in rom.iter().enumerate() {
+        let addr = base + i;
+        if addr < c.mem.len() {
+            c.mem[addr] = w;
+            c.mark_written(addr);
+        }
+    }
+}
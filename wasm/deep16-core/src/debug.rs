@@ -0,0 +1,173 @@
+//! Debugger support: execution breakpoints, optionally conditional, and data
+//! watchpoints, both checked by `Cpu::run`.
+
+use crate::cpu::{phys, Cpu, StopReason};
+use crate::expr::Expr;
+
+/// Where an execution breakpoint sits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakAt {
+    /// Physical word address, however CS:PC reaches it.
+    Phys(usize),
+    /// A CS:PC pair of the active context.
+    CsPc(u16, u16),
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Breakpoint {
+    pub(crate) id: u32,
+    pub(crate) at: BreakAt,
+    pub(crate) condition: Option<Expr>,
+    /// Times the breakpoint was reached with its condition true.
+    pub(crate) hits: u32,
+    /// Number of such hits to let pass before stopping.
+    pub(crate) ignore: u32,
+}
+
+/// Which data accesses a watchpoint reacts to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    /// Reads and writes.
+    Access,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Watchpoint {
+    pub(crate) id: u32,
+    /// Inclusive physical address range.
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) kind: WatchKind,
+}
+
+/// The data access that triggered a watchpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchHit {
+    pub id: u32,
+    /// The accessing instruction: its word, physical address and CS:PC.
+    pub instr: u16,
+    pub instr_addr: usize,
+    pub cs: u16,
+    pub pc: u16,
+    /// Physical word address accessed.
+    pub addr: usize,
+    pub is_store: bool,
+    /// Memory contents before and after the access (equal for reads).
+    pub old_value: u16,
+    pub new_value: u16,
+    /// Segment register used: 0=CS, 1=DS, 2=SS, 3=ES, and its value.
+    pub seg_idx: u16,
+    pub seg_val: u16,
+}
+
+/// Breakpoint and watchpoint tables plus the state `run` needs to step off
+/// a breakpoint.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Debugger {
+    pub(crate) breakpoints: Vec<Breakpoint>,
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    pub(crate) watch_hit: Option<WatchHit>,
+    /// Ids are shared by breakpoints and watchpoints.
+    next_id: u32,
+    /// Set while `run` is executing; single steps ignore breakpoints and
+    /// watchpoints.
+    pub(crate) armed: bool,
+    /// Address of the breakpoint we last stopped at. The next fetch from it
+    /// goes ahead, so `run` can resume from a breakpoint.
+    pub(crate) resume_addr: Option<usize>,
+}
+
+impl Debugger {
+    pub(crate) fn add(&mut self, at: BreakAt) -> u32 {
+        self.next_id += 1;
+        self.breakpoints.push(Breakpoint { id: self.next_id, at, condition: None, hits: 0, ignore: 0 });
+        self.next_id
+    }
+
+    pub(crate) fn add_watch(&mut self, start: usize, end: usize, kind: WatchKind) -> u32 {
+        self.next_id += 1;
+        let (start, end) = if start <= end { (start, end) } else { (end, start) };
+        self.watchpoints.push(Watchpoint { id: self.next_id, start, end, kind });
+        self.next_id
+    }
+
+    pub(crate) fn breakpoint_mut(&mut self, id: u32) -> Option<&mut Breakpoint> {
+        self.breakpoints.iter_mut().find(|b| b.id == id)
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) -> bool {
+        let before = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.id != id);
+        self.breakpoints.len() != before
+    }
+
+    pub(crate) fn remove_watch(&mut self, id: u32) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.id != id);
+        self.watchpoints.len() != before
+    }
+}
+
+/// Called before the instruction at `cs:pc` is fetched. Stops the machine and
+/// returns `true` if a breakpoint is set there whose condition holds and whose
+/// ignore count is used up.
+pub(crate) fn break_before(c: &mut Cpu, cs: u16, pc: u16) -> bool {
+    let d = &mut c.debug;
+    if !d.armed || d.breakpoints.is_empty() { return false; }
+    let pa = phys(cs, pc as u32);
+    if d.resume_addr.take() == Some(pa) { return false; }
+    // Conditions read the machine, so evaluate them with the table taken out.
+    let mut bps = std::mem::take(&mut c.debug.breakpoints);
+    let mut hit = None;
+    for bp in bps.iter_mut() {
+        let here = match bp.at {
+            BreakAt::Phys(addr) => addr == pa,
+            BreakAt::CsPc(bcs, bpc) => bcs == cs && bpc == pc,
+        };
+        if !here || bp.condition.as_ref().is_some_and(|e| e.eval(c) == 0) { continue; }
+        bp.hits += 1;
+        if bp.hits > bp.ignore && hit.is_none() { hit = Some(bp.id); }
+    }
+    c.debug.breakpoints = bps;
+    let Some(id) = hit else { return false; };
+    c.debug.resume_addr = Some(pa);
+    c.running = false;
+    c.stop_reason = Some(StopReason::Breakpoint { id, addr: pa });
+    true
+}
+
+/// Called after a data access by the current instruction. If a watchpoint
+/// covers it, records the hit and stops the machine once the instruction has
+/// finished.
+pub(crate) fn check_watch(c: &mut Cpu, addr: usize, is_store: bool, old_value: u16, new_value: u16, seg_idx: u16, seg_val: u16) {
+    let d = &mut c.debug;
+    if !d.armed || d.watchpoints.is_empty() { return; }
+    let hit = d.watchpoints.iter().find(|w| {
+        let kind_ok = match w.kind {
+            WatchKind::Read => !is_store,
+            WatchKind::Write => is_store,
+            WatchKind::Access => true,
+        };
+        kind_ok && (w.start..=w.end).contains(&addr)
+    });
+    let Some(w) = hit else { return; };
+    let id = w.id;
+    let d = &mut c.debug;
+    d.watch_hit = Some(WatchHit {
+        id,
+        instr: c.fetch_instr,
+        instr_addr: c.fetch_addr,
+        cs: c.fetch_cs,
+        pc: c.fetch_pc,
+        addr,
+        is_store,
+        old_value,
+        new_value,
+        seg_idx,
+        seg_val,
+    });
+    c.running = false;
+    c.stop_reason = Some(StopReason::Watchpoint { id, addr });
+}
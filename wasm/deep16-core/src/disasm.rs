@@ -0,0 +1,161 @@
+//! Instruction classes and text disassembly, decoded the way `exec` decodes
+//! and printed in the syntax of js/deep16_disassembler.js.
+
+/// Instruction groups by major opcode (the number of leading one bits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InstrClass {
+    Ldi,
+    /// LD/ST.
+    Mem,
+    Alu,
+    Jump,
+    LdsSts,
+    /// MOV and its JMP/LNK/ALNK/AMV forms.
+    Mov,
+    Lsi,
+    /// Single-operand group: SWB, INV, NEG, JML, SRS/SRD/ERS/ERD, SET/CLR.
+    Sop,
+    Mvs,
+    Smv,
+    Lpsw,
+    /// NOP, FSH, SWI, RETI.
+    Sys,
+    Halt,
+    /// Unassigned major opcodes.
+    Undefined,
+}
+
+impl InstrClass {
+    pub fn of(instr: u16) -> InstrClass {
+        match instr.leading_ones() {
+            0 => InstrClass::Ldi,
+            1 => InstrClass::Mem,
+            2 => InstrClass::Alu,
+            3 => InstrClass::Jump,
+            4 => InstrClass::LdsSts,
+            5 => InstrClass::Mov,
+            6 => InstrClass::Lsi,
+            7 => InstrClass::Sop,
+            8 => InstrClass::Mvs,
+            10 => InstrClass::Smv,
+            11 => InstrClass::Lpsw,
+            12 => InstrClass::Sys,
+            16 => InstrClass::Halt,
+            _ => InstrClass::Undefined,
+        }
+    }
+
+    /// This class's bit in a class mask.
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+const REG: [&str; 16] = ["R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12", "R13", "R14", "PC"];
+const SEG: [&str; 4] = ["CS", "DS", "SS", "ES"];
+const JUMP: [&str; 8] = ["JZ", "JNZ", "JC", "JNC", "JN", "JNN", "JO", "JNO"];
+const ALU: [&str; 32] = [
+    "ADD", "ADD", "SUB", "SUB", "CMP", "CMP", "AND", "AND", "TBC", "TBC", "OR", "OR", "XOR", "XOR", "TBS", "TBS",
+    "SL", "SLA", "SLAC", "SLC", "SR", "SRC", "SRA", "SRAC", "ROL", "RLC", "ROR", "RRC", "MUL", "MUL32", "DIV", "DIV32",
+];
+const SMV: [&str; 6] = ["ACS", "ADS", "ASS", "AES", "APC", "APSW"];
+
+fn unknown(instr: u16) -> String {
+    format!("??? (0x{instr:04X})")
+}
+
+/// Disassembles one instruction word.
+pub fn disassemble(instr: u16) -> String {
+    let r = |shift: u16| REG[((instr >> shift) & 0xF) as usize];
+    match InstrClass::of(instr) {
+        InstrClass::Ldi => format!("LDI #0x{:04X}", instr & 0x7FFF),
+        InstrClass::Mem => {
+            let op = if instr & 0x2000 != 0 { "ST" } else { "LD" };
+            format!("{op} {}, [{}+0x{:X}]", r(9), r(5), instr & 0x1F)
+        }
+        InstrClass::Alu => {
+            let func5 = (instr >> 8) & 0x1F;
+            let name = ALU[func5 as usize];
+            let is_reg = (func5 < 0b10000 && func5 & 1 == 0) || func5 >= 0b11100;
+            if is_reg { format!("{name} {}, {}", r(4), r(0)) } else { format!("{name} {}, #0x{:X}", r(4), instr & 0xF) }
+        }
+        InstrClass::Jump => {
+            let off = jump_offset(instr);
+            let sign = if off >= 0 { "+" } else { "" };
+            format!("{} {sign}{off}", JUMP[((instr >> 9) & 0x7) as usize])
+        }
+        InstrClass::LdsSts => {
+            let op = if instr & 0x0400 != 0 { "STS" } else { "LDS" };
+            format!("{op} {}, {}, {}", r(4), SEG[((instr >> 8) & 0x3) as usize], r(0))
+        }
+        InstrClass::Mov => {
+            let (rd, rs, imm) = ((instr >> 6) & 0xF, (instr >> 2) & 0xF, instr & 0x3);
+            match (rd, rs, imm) {
+                (15, _, 0) => format!("JMP {}", r(2)),
+                (14, 15, 2) => "LINK".into(),
+                (_, 15, 2) => format!("LNK {}", r(6)),
+                (14, 15, 3) => "ALINK".into(),
+                (_, 15, 3) => format!("ALNK {}", r(6)),
+                (_, _, 3) => format!("AMV {}, {}", r(6), r(2)),
+                (_, _, 0) => format!("MOV {}, {}", r(6), r(2)),
+                _ => format!("MOV {}, {}, #0x{imm:X}", r(6), r(2)),
+            }
+        }
+        InstrClass::Lsi => {
+            let imm = ((instr as i16) << 11) >> 11;
+            let sign = if imm < 0 { "-" } else { "" };
+            format!("LSI {}, #{sign}0x{:X}", r(5), imm.unsigned_abs())
+        }
+        InstrClass::Sop => {
+            let imm = instr & 0xF;
+            match (instr >> 4) & 0xF {
+                0b0000 => format!("SWB {}", r(0)),
+                0b0001 => format!("INV {}", r(0)),
+                0b0010 => format!("NEG {}", r(0)),
+                0b0100 => format!("JML {}", r(0)),
+                0b1000 => format!("SRS {}", r(0)),
+                0b1001 => format!("SRD {}", r(0)),
+                0b1010 => format!("ERS {}", r(0)),
+                0b1011 => format!("ERD {}", r(0)),
+                0b1100 => format!("SET #0x{imm:X}"),
+                0b1101 => format!("CLR #0x{imm:X}"),
+                0b1110 | 0b1111 if imm >= 12 => unknown(instr),
+                0b1110 => format!("SET2 #0x{imm:X}"),
+                0b1111 => format!("CLR2 #0x{imm:X}"),
+                _ => unknown(instr),
+            }
+        }
+        InstrClass::Mvs => {
+            let seg = SEG[(instr & 0x3) as usize];
+            if instr & 0x40 != 0 { format!("MVS {seg}, {}", r(2)) } else { format!("MVS {}, {seg}", r(2)) }
+        }
+        InstrClass::Smv => match SMV.get((instr & 0xF) as usize) {
+            Some(alt) if instr & 0x10 != 0 => format!("SMV R0, {alt}"),
+            Some(alt) => format!("SMV {alt}"),
+            None => unknown(instr),
+        },
+        InstrClass::Lpsw => format!("LPSW {}", r(0)),
+        InstrClass::Sys => match instr & 0x7 {
+            0 => "NOP".into(),
+            1 => "FSH".into(),
+            2 => "SWI".into(),
+            3 => "RETI".into(),
+            _ => unknown(instr),
+        },
+        InstrClass::Halt => "HLT".into(),
+        InstrClass::Undefined => unknown(instr),
+    }
+}
+
+/// Like `disassemble`, but also shows the target of a relative jump fetched
+/// from offset `pc`.
+pub fn disassemble_at(instr: u16, pc: u16) -> String {
+    let text = disassemble(instr);
+    if InstrClass::of(instr) != InstrClass::Jump { return text; }
+    let target = pc.wrapping_add(1).wrapping_add(jump_offset(instr) as u16);
+    format!("{text}   ; 0x{target:04X}")
+}
+
+fn jump_offset(instr: u16) -> i16 {
+    ((instr as i16) << 7) >> 7
+}
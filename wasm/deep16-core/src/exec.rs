@@ -0,0 +1,684 @@
+use crate::bus;
+use crate::cpu::{
+    phys, Cpu, IllegalPolicy, StopReason, EVENT_IRQ, EVENT_NMI, EVENT_RETI, EVENT_SWI, EVENT_TRAP, HW_INT_VECTOR,
+    ILLEGAL_VECTOR, NMI_VECTOR, SWI_VECTOR,
+};
+use crate::debug::{break_before, check_watch};
+use crate::history;
+use crate::pipeline;
+use crate::trace;
+
+fn is_stack_register(psw: u16, idx: usize) -> bool {
+    let sr = ((psw >> 6) & 0xF) as usize;
+    if sr == 0 { return false; }
+    let dual = (psw & (1 << 10)) != 0;
+    if dual { idx == sr || idx == (sr + 1) } else { idx == sr }
+}
+
+fn is_extra_register(psw: u16, idx: usize) -> bool {
+    let er = ((psw >> 11) & 0xF) as usize;
+    if er == 0 { return false; }
+    let dual = (psw & (1 << 15)) != 0;
+    if dual { idx == er || idx == (er + 1) } else { idx == er }
+}
+
+fn update_psw_flags(c: &mut Cpu) {
+    if !c.last_op_alu { return; }
+    let mut psw = c.psw & 0xFFF0;
+    let res16 = (c.last_alu_result as i64) & 0xFFFF;
+    let signed = if (res16 & 0x8000) != 0 { res16 - 0x10000 } else { res16 };
+    if res16 == 0 { psw |= 1 << 1; }
+    if (res16 & 0x8000) != 0 { psw |= 1 << 0; }
+    if c.last_alu_result > 0xFFFF || c.last_alu_result < 0 { psw |= 1 << 3; }
+    if !(-32768..=32767).contains(&signed) { psw |= 1 << 2; }
+    c.psw = psw;
+    c.last_op_alu = false;
+}
+
+fn exec_ldi(c: &mut Cpu, instr: u16) {
+    let imm = instr & 0x7FFF;
+    c.reg[0] = imm;
+    c.last_alu_result = imm as i32;
+    c.last_op_alu = true;
+}
+
+fn exec_mem(c: &mut Cpu, instr: u16) {
+    let d = (instr >> 13) & 0x1;
+    let rd = ((instr >> 9) & 0xF) as usize;
+    let rb = ((instr >> 5) & 0xF) as usize;
+    let off = (instr & 0x1F) as u32;
+    let addr_off = (c.reg[rb] as u32).wrapping_add(off);
+    let seg_idx = if is_stack_register(c.psw, rb) { 2u16 } else if is_extra_register(c.psw, rb) { 3u16 } else { 1u16 };
+    let seg = c.seg(seg_idx);
+    let pa = phys(seg, addr_off);
+    if pa >= c.mem.len() && !c.bus.is_mapped(pa) { stop(c, StopReason::PhysicalAddressOutOfRange { addr: pa }); return; }
+    if d == 0 { c.reg[rd] = read_mem(c, pa, seg_idx, seg); } else { write_mem(c, pa, c.reg[rd], seg_idx, seg); }
+    c.recent_addr = pa;
+    c.recent_base = c.reg[rb];
+    c.recent_offset = (off & 0x1F) as u16;
+    c.recent_seg_val = seg;
+    c.recent_seg_idx = seg_idx;
+    c.recent_is_store = d == 1;
+}
+
+/// Data read through segment `seg_idx` (value `seg`); `pa` must be in range
+/// or claimed by a device.
+fn read_mem(c: &mut Cpu, pa: usize, seg_idx: u16, seg: u16) -> u16 {
+    let v = match c.bus.read(pa) {
+        Some(v) => v,
+        None => c.mem[pa],
+    };
+    trace::note_access(c, pa, v, false);
+    check_watch(c, pa, false, v, v, seg_idx, seg);
+    v
+}
+
+/// Data write through segment `seg_idx` (value `seg`); `pa` must be in range
+/// or claimed by a device. Device writes are not journaled for `step_back`.
+fn write_mem(c: &mut Cpu, pa: usize, value: u16, seg_idx: u16, seg: u16) {
+    let old = c.read_word(pa);
+    if !c.bus.write(pa, value) {
+        history::note_write(c, pa);
+        c.mem[pa] = value;
+        c.mark_written(pa);
+    }
+    trace::note_access(c, pa, value, true);
+    check_watch(c, pa, true, old, value, seg_idx, seg);
+}
+
+fn exec_alu(c: &mut Cpu, instr: u16) {
+    let func5 = (instr >> 8) & 0x1F;
+    let rd = ((instr >> 4) & 0xF) as usize;
+    let low4 = instr & 0xF;
+    // MUL32/DIV32 use Rd:Rd+1 and need an even Rd
+    if (func5 == 0b11101 || func5 == 0b11111) && !rd.is_multiple_of(2) {
+        stop(c, StopReason::OddRegisterPairViolation { addr: c.fetch_addr, instr });
+        return;
+    }
+    let rdv = c.reg[rd] as u32 & 0xFFFF;
+    let sign = (rdv & 0x8000) != 0;
+    let is_reg = func5 == 0b00000 || func5 == 0b00010 || func5 == 0b00100 || func5 == 0b00110 || func5 == 0b01000 || func5 == 0b01010 || func5 == 0b01100 || func5 == 0b01110 || func5 >= 0b11100;
+    let opv = if is_reg { c.reg[low4 as usize] as u32 & 0xFFFF } else { low4 as u32 & 0xF };
+    let mut result: i32 = rdv as i32;
+    match func5 {
+        0b00000 | 0b00001 => { result = ((rdv + opv) & 0x1FFFF) as i32; }
+        0b00010 | 0b00011 => { result = rdv as i32 - opv as i32; }
+        0b00100 | 0b00101 => { result = rdv as i32 - opv as i32; c.last_alu_result = result; c.last_op_alu = true; return; }
+        0b00110 | 0b00111 => { result = ((rdv & opv) & 0xFFFF) as i32; }
+        0b01000 => {
+            let masked = (rdv & opv) & 0xFFFF;
+            c.last_alu_result = if masked == 0 { 0 } else { 1 };
+            c.last_op_alu = true;
+            return;
+        }
+        0b01001 => {
+            let bit = ((rdv >> opv) & 1) as i32;
+            c.last_alu_result = if bit == 0 { 1 } else { 0 };
+            c.last_op_alu = true;
+            return;
+        }
+        0b01010 | 0b01011 => { result = ((rdv | opv) & 0xFFFF) as i32; }
+        0b01100 | 0b01101 => { result = ((rdv ^ opv) & 0xFFFF) as i32; }
+        0b01110 => {
+            let masked = (rdv & opv) & 0xFFFF;
+            c.last_alu_result = if masked != 0 { 1 } else { 0 };
+            c.last_op_alu = true;
+            return;
+        }
+        0b01111 => {
+            let bit = ((rdv >> opv) & 1) as i32;
+            c.last_alu_result = if bit == 1 { 1 } else { 0 };
+            c.last_op_alu = true;
+            return;
+        }
+        0b10000 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
+            result = ((rdv << count) & 0xFFFF) as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10001 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
+            let mut val = ((rdv << count) & 0x7FFF) as u16;
+            if sign { val |= 0x8000; }
+            result = val as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10010 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
+            let carry_in = (c.psw >> 3) & 1;
+            let mut val = ((rdv << count) & 0x7FFF) as u16;
+            if sign { val |= 0x8000; }
+            if count > 0 { val |= carry_in << (count - 1); }
+            result = val as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10011 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
+            let carry_in = (c.psw >> 3) & 1;
+            let mut val = ((rdv << count) & 0xFFFF) as u16;
+            if count > 0 { val |= carry_in << (count - 1); }
+            result = val as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10100 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
+            result = (rdv >> count) as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10101 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
+            let carry_in = (c.psw >> 3) & 1;
+            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
+            result = ((rdv >> count) | fill) as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10110 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
+            let sign_mask = if sign { 0xFFFFu32 << (16 - count) } else { 0 };
+            result = ((rdv >> count) | (sign_mask & 0xFFFF)) as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b10111 => {
+            let count = opv & 0xF;
+            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
+            let sign_mask = if sign { 0xFFFFu32 << (16 - count) } else { 0 };
+            let carry_in = (c.psw >> 3) & 1;
+            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
+            result = ((rdv >> count) | (sign_mask & 0xFFFF) | fill) as i32;
+            c.psw = (c.psw & !0x8) | (carry_out << 3);
+        }
+        0b11000 => {
+            let count = opv & 0xF;
+            result = (((rdv << count) | (rdv >> (16 - count))) & 0xFFFF) as i32;
+        }
+        0b11001 => {
+            let count = opv & 0xF;
+            let carry_in = (c.psw >> 3) & 1;
+            let fill = if count > 0 { (carry_in as u32) << (count - 1) } else { 0 };
+            result = (((rdv << count) | (rdv >> (16 - count)) | fill) & 0xFFFF) as i32;
+        }
+        0b11010 => {
+            let count = opv & 0xF;
+            result = (((rdv >> count) | (rdv << (16 - count))) & 0xFFFF) as i32;
+        }
+        0b11011 => {
+            let count = opv & 0xF;
+            let carry_in = (c.psw >> 3) & 1;
+            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
+            let new_carry = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { carry_in };
+            result = (((rdv >> count) | (rdv << (16 - count)) | fill) & 0xFFFF) as i32;
+            c.psw = (c.psw & !0x8) | (new_carry << 3);
+        }
+        0b11100 => {
+            let prod = ((rdv & 0xFFFF) * (opv & 0xFFFF)) & 0xFFFF;
+            c.reg[rd] = (prod & 0xFFFF) as u16;
+            result = prod as i32;
+        }
+        0b11101 => {
+            let prod = (rdv as u64) * (opv as u64);
+            c.reg[rd] = ((prod >> 16) as u32 & 0xFFFF) as u16;
+            c.reg[rd + 1] = (prod as u32 & 0xFFFF) as u16;
+            result = prod as i32;
+        }
+        0b11110 => {
+            if let (Some(q), Some(r)) = (rdv.checked_div(opv), rdv.checked_rem(opv)) {
+                c.reg[rd] = (q & 0xFFFF) as u16;
+                if let Some(rem) = c.reg.get_mut(rd + 1) { *rem = (r & 0xFFFF) as u16; }
+                result = (q & 0xFFFF) as i32;
+            } else {
+                result = 0xFFFF;
+            }
+        }
+        0b11111 => {
+            if opv == 0 { result = 0xFFFF; } else {
+                let dividend = (((c.reg[rd] as u32) << 16) | (c.reg[rd + 1] as u32)) as u64;
+                let q = (dividend / opv as u64) as u32 & 0xFFFF;
+                let r = (dividend % opv as u64) as u32 & 0xFFFF;
+                c.reg[rd] = q as u16;
+                c.reg[rd + 1] = r as u16;
+                result = q as i32;
+            }
+        }
+        _ => { }
+    }
+    c.reg[rd] = (result as u32 & 0xFFFF) as u16;
+    c.last_alu_result = result;
+    c.last_op_alu = true;
+}
+
+fn exec_mov(c: &mut Cpu, instr: u16, original_pc: u16) -> bool {
+    let rd = ((instr >> 6) & 0xF) as usize;
+    let rs = ((instr >> 2) & 0xF) as usize;
+    let imm2 = instr & 0x3;
+
+    let value: u16 = if imm2 == 0 {
+        c.reg[rs]
+    } else if rs == 15 && imm2 == 2 {
+        // Standard link (LNK): PC architectural read before jump
+        original_pc.wrapping_add(2)
+    } else if rs == 15 && imm2 == 3 {
+        // Architectural link in delay slot (ALNK): next instruction after delay slot
+        original_pc.wrapping_add(1)
+    } else if imm2 == 3 {
+        // Architectural read bypass (AMV)
+        c.reg[rs]
+    } else {
+        c.reg[rs].wrapping_add(imm2)
+    };
+
+    // MOV to PC is a branch with one delay slot
+    if rd == 15 {
+        c.delay_active = true;
+        c.delayed_pc = value;
+        c.delayed_cs = c.seg(0);
+        c.delayed_to_shadow = c.in_shadow();
+        c.branch_taken = true;
+        c.last_alu_result = value as i32;
+        c.last_op_alu = true;
+        return true;
+    }
+
+    c.reg[rd] = value;
+    c.last_alu_result = value as i32;
+    c.last_op_alu = true;
+    false
+}
+
+fn exec_lsi(c: &mut Cpu, instr: u16) {
+    let rd = ((instr >> 5) & 0xF) as usize;
+    let mut imm = (instr & 0x1F) as i16;
+    if (imm & 0x10) != 0 { imm |= -1i16 << 5; }
+    c.reg[rd] = imm as u16;
+}
+
+fn exec_sop(c: &mut Cpu, instr: u16) -> bool {
+    let t = (instr >> 4) & 0xF;
+    let rx = (instr & 0xF) as usize;
+    match t {
+        0b0000 => {
+            let v = c.reg[rx];
+            let swapped = ((v & 0x00FF) << 8) | ((v >> 8) & 0x00FF);
+            c.reg[rx] = swapped;
+            c.last_alu_result = swapped as i32;
+            c.last_op_alu = true;
+            false
+        }
+        0b0001 => {
+            c.reg[rx] = !c.reg[rx];
+            c.last_alu_result = c.reg[rx] as i32;
+            c.last_op_alu = true;
+            false
+        }
+        0b0010 => {
+            let v = c.reg[rx];
+            c.reg[rx] = v.wrapping_neg();
+            c.last_alu_result = -(v as i32);
+            c.last_op_alu = true;
+            false
+        }
+        0b0100 => {
+            if !rx.is_multiple_of(2) {
+                stop(c, StopReason::OddRegisterPairViolation { addr: c.fetch_addr, instr });
+                return false;
+            }
+            let target_cs = c.reg[rx];
+            let target_pc = c.reg[rx + 1];
+            c.delay_active = true;
+            c.delayed_pc = target_pc;
+            c.delayed_cs = target_cs;
+            c.delayed_to_shadow = c.in_shadow();
+            c.branch_taken = true;
+            // Far jump: the new CS takes effect once the delay slot is done
+            pipeline::flush(c);
+            true
+        }
+        0b1000 => {
+            c.psw = (c.psw & !0x03C0) | (((rx as u16) & 0xF) << 6);
+            false
+        }
+        0b1001 => {
+            c.psw = (c.psw & !0x03C0) | (((rx as u16) & 0xF) << 6) | 0x0400;
+            false
+        }
+        0b1010 => {
+            c.psw = (c.psw & !0x7800) | (((rx as u16) & 0xF) << 11);
+            false
+        }
+        0b1011 => {
+            c.psw = (c.psw & !0x7800) | (((rx as u16) & 0xF) << 11) | 0x8000;
+            false
+        }
+        // SET/CLR address PSW bits 0-15, SET2/CLR2 bits 4-15 (imm + 4); the
+        // SET2/CLR2 immediates 12-15 would name bits past the PSW and are illegal
+        0b1100 => {
+            c.psw |= psw_bit(rx);
+            false
+        }
+        0b1101 => {
+            c.psw &= !psw_bit(rx);
+            false
+        }
+        0b1110 | 0b1111 if rx >= 12 => { illegal(c, instr); false }
+        0b1110 => {
+            c.psw |= psw_bit(rx + 4);
+            false
+        }
+        0b1111 => {
+            c.psw &= !psw_bit(rx + 4);
+            false
+        }
+        _ => { illegal(c, instr); false }
+    }
+}
+
+fn psw_bit(n: usize) -> u16 {
+    if n < 16 { 1 << n } else { 0 }
+}
+
+fn exec_mvs(c: &mut Cpu, instr: u16) {
+    let d = (instr >> 6) & 0x1;
+    let rd = ((instr >> 2) & 0xF) as usize;
+    let seg = instr & 0x3;
+    if d == 0 {
+        c.reg[rd] = c.seg(seg);
+    } else {
+        *c.seg_mut(seg) = c.reg[rd];
+    }
+}
+
+// SMV always talks to the context that is *not* active: the shadow registers
+// from normal view, the normal registers from shadow view. PSW' holds the
+// other context's PSW in both views, so APSW maps to it either way.
+fn exec_smv(c: &mut Cpu, instr: u16) {
+    let d = (instr >> 4) & 0x1;
+    let sel = instr & 0xF;
+    let in_shadow = c.in_shadow();
+    let r0 = c.reg[0];
+    let alt = match (sel, in_shadow) {
+        (0, false) => &mut c.scs,
+        (1, false) => &mut c.sds,
+        (2, false) => &mut c.sss,
+        (3, false) => &mut c.ses,
+        (4, false) => &mut c.spc,
+        (0, true) => &mut c.cs,
+        (1, true) => &mut c.ds,
+        (2, true) => &mut c.ss,
+        (3, true) => &mut c.es,
+        (4, true) => &mut c.reg[15],
+        (5, _) => &mut c.spsw,
+        // 0110-1111 are reserved: no register is read or written
+        _ => { illegal(c, instr); return; }
+    };
+    if d == 0 {
+        *alt = r0;
+    } else {
+        let v = *alt;
+        c.reg[0] = v;
+    }
+}
+
+fn exec_lpsw(c: &mut Cpu, instr: u16) {
+    let rx = (instr & 0xF) as usize;
+    c.reg[rx] = c.psw;
+}
+
+fn exec_jump(c: &mut Cpu, instr: u16) -> bool {
+    let cond = (instr >> 9) & 0x7;
+    let mut off = instr & 0x1FF;
+    if (off & 0x100) != 0 { off = (off as i32 - 0x200) as u16; }
+    let z = (c.psw & (1 << 1)) != 0;
+    let cflag = (c.psw & (1 << 3)) != 0;
+    let nflag = (c.psw & (1 << 0)) != 0;
+    let oflag = (c.psw & (1 << 2)) != 0;
+    let j = match cond {
+        0 => z,
+        1 => !z,
+        2 => cflag,
+        3 => !cflag,
+        4 => nflag,
+        5 => !nflag,
+        6 => oflag,
+        _ => !oflag,
+    };
+    if j {
+        let in_shadow = c.in_shadow();
+        let current_pc = if in_shadow { c.spc } else { c.reg[15] } as i32;
+        let target_pc = (current_pc + (off as i16 as i32)) as u16;
+        c.delay_active = true;
+        c.delayed_pc = target_pc;
+        c.delayed_cs = c.seg(0);
+        c.delayed_to_shadow = in_shadow;
+        c.branch_taken = true;
+    } else {
+        c.branch_taken = false;
+    }
+    true
+}
+
+/// Stops the machine, recording why.
+fn stop(c: &mut Cpu, reason: StopReason) {
+    c.running = false;
+    c.stop_reason = Some(reason);
+}
+
+/// Reads the instruction at `cs:pc`, stopping the machine if the word lies
+/// outside memory or has never been written. Device registers are always
+/// readable; they are peeked, so executing one has no read side effects.
+fn fetch(c: &mut Cpu, cs: u16, pc: u16) -> Option<u16> {
+    let pa = phys(cs, pc as u32);
+    c.fetch_addr = pa;
+    c.fetch_cs = cs;
+    c.fetch_pc = pc;
+    c.debug.resume_addr = None;
+    let instr = match c.bus.peek(pa) {
+        Some(instr) => instr,
+        None if pa >= c.mem.len() => { stop(c, StopReason::PhysicalAddressOutOfRange { addr: pa }); return None; }
+        None if !c.is_written(pa) => { stop(c, StopReason::FetchFromUninitialized { addr: pa }); return None; }
+        None => c.mem[pa],
+    };
+    c.fetch_instr = instr;
+    Some(instr)
+}
+
+/// Executes one step (an instruction or an interrupt entry) and journals it.
+pub(crate) fn step_one(c: &mut Cpu) -> bool {
+    history::begin(c);
+    trace::begin(c);
+    let running = execute_step(c);
+    trace::finish(c);
+    history::commit(c);
+    running
+}
+
+fn execute_step(c: &mut Cpu) -> bool {
+    if !c.running { c.running = true; c.stop_reason = None; }
+    pipeline::begin_step(c);
+    let in_shadow = c.in_shadow();
+    if c.delay_active {
+        let active_cs = c.seg(0);
+        let active_pc = if in_shadow { c.spc } else { c.reg[15] };
+        if break_before(c, active_cs, active_pc) { return false; }
+        let Some(instr) = fetch(c, active_cs, active_pc) else { return false; };
+        if instr == 0xFFFF { stop(c, StopReason::Halted); return false; }
+        c.delay_active = false;
+        c.last_event_code = instr;
+        c.last_event_spc = active_pc;
+        c.last_event_scs = active_cs;
+        let original_pc = active_pc;
+        if in_shadow { c.spc = c.spc.wrapping_add(1); } else { c.reg[15] = c.reg[15].wrapping_add(1); }
+        c.last_op_alu = false;
+        c.last_alu_result = 0;
+        pipeline::issue(c, instr);
+        trace::issue(c, instr);
+        let _is_branch = exec_instruction(c, instr, original_pc);
+        update_psw_flags(c);
+        if !c.running && !matches!(c.stop_reason, Some(StopReason::Watchpoint { .. })) {
+            // A fault in the delay slot leaves PC on the slot with the branch
+            // still pending, so resuming retries the slot and then branches.
+            if in_shadow { c.spc = original_pc; } else { c.reg[15] = original_pc; }
+            c.delay_active = true;
+        } else if c.branch_taken {
+            if c.delayed_to_shadow { c.spc = c.delayed_pc; c.scs = c.delayed_cs; } else { c.reg[15] = c.delayed_pc; c.cs = c.delayed_cs; }
+        }
+        bus::tick(c);
+        return c.running;
+    }
+    if c.nmi_pending && !c.nmi_active {
+        c.nmi_pending = false;
+        c.nmi_active = true;
+        enter_interrupt(c, NMI_VECTOR);
+        c.last_event_code = EVENT_NMI;
+        bus::tick(c);
+        return true;
+    }
+    if (c.irq_pending || c.irq_line || c.bus.irq()) && (c.psw & (1 << 4)) != 0 && !in_shadow {
+        c.irq_pending = false;
+        enter_interrupt(c, HW_INT_VECTOR);
+        c.last_event_code = EVENT_IRQ;
+        bus::tick(c);
+        return true;
+    }
+    let active_cs = c.seg(0);
+    let active_pc = if in_shadow { c.spc } else { c.reg[15] };
+    if break_before(c, active_cs, active_pc) { return false; }
+    let Some(instr) = fetch(c, active_cs, active_pc) else { return false; };
+    if instr == 0xFFFF { stop(c, StopReason::Halted); return false; }
+    c.last_event_code = instr;
+    c.last_event_spc = active_pc;
+    c.last_event_scs = active_cs;
+    let original_pc = active_pc;
+    if in_shadow { c.spc = c.spc.wrapping_add(1); } else { c.reg[15] = c.reg[15].wrapping_add(1); }
+    c.last_op_alu = false;
+    c.last_alu_result = 0;
+    pipeline::issue(c, instr);
+    trace::issue(c, instr);
+    let _is_branch = exec_instruction(c, instr, original_pc);
+    update_psw_flags(c);
+    bus::tick(c);
+    c.running
+}
+
+fn exec_instruction(c: &mut Cpu, instr: u16, original_pc: u16) -> bool {
+    // Fast-path: HLT and the SYS group (NOP/FSH/SWI/RETI)
+    if instr == 0xFFFF { stop(c, StopReason::Halted); return false; }
+    if (instr >> 3) == 0x1FFE { exec_sys(c, instr); return false; }
+    if (instr & 0x8000) == 0 { exec_ldi(c, instr); return false; }
+    if ((instr >> 14) & 0x3) == 0b10 { exec_mem(c, instr); return false; }
+    let opcode3 = (instr >> 13) & 0x7;
+    match opcode3 {
+        0b110 => { exec_alu(c, instr); false }
+        0b111 => {
+            if ((instr >> 12) & 0xF) == 0b1110 { return exec_jump(c, instr); }
+            if ((instr >> 11) & 0x1F) == 0b11110 { exec_lds_sts(c, instr); return false; }
+            if ((instr >> 10) & 0x3F) == 0b111110 { return exec_mov(c, instr, original_pc); }
+            if ((instr >> 9) & 0x7F) == 0b1111110 { exec_lsi(c, instr); return false; }
+            if ((instr >> 8) & 0xFF) == 0b11111110 { return exec_sop(c, instr); }
+            if ((instr >> 7) & 0x1FF) == 0b111111110 { exec_mvs(c, instr); return false; }
+            if ((instr >> 5) & 0x7FF) == 0b11111111110 { exec_smv(c, instr); return false; }
+            if ((instr >> 4) & 0xFFF) == 0b111111111110 { exec_lpsw(c, instr); return false; }
+            // 0xFF80-0xFFBF and 0xFFF8-0xFFFE are unassigned
+            illegal(c, instr);
+            false
+        }
+        _ => false,
+    }
+}
+
+fn exec_lds_sts(c: &mut Cpu, instr: u16) {
+    // [11110][d1][seg2][Rd4][Rs4]
+    let d = (instr >> 10) & 0x1;
+    let seg = (instr >> 8) & 0x3;
+    let rd = ((instr >> 4) & 0xF) as usize;
+    let rs = (instr & 0xF) as usize;
+    let base = c.reg[rs] as u32;
+    let segv = c.seg(seg);
+    let pa = phys(segv, base);
+    if pa >= c.mem.len() && !c.bus.is_mapped(pa) { stop(c, StopReason::PhysicalAddressOutOfRange { addr: pa }); return; }
+    if d == 0 { c.reg[rd] = read_mem(c, pa, seg, segv); } else { write_mem(c, pa, c.reg[rd], seg, segv); }
+    c.recent_addr = pa;
+    c.recent_base = c.reg[rs];
+    c.recent_offset = 0;
+    c.recent_seg_val = segv;
+    c.recent_seg_idx = seg;
+    c.recent_is_store = d == 1;
+}
+
+fn exec_sys(c: &mut Cpu, instr: u16) -> bool {
+    let op = instr & 0x7;
+    match op {
+        0 => { /* NOP */ false }
+        1 => { /* FSH */
+            flush_pipeline(c);
+            false
+        }
+        2 => { /* SWI */
+            enter_interrupt(c, SWI_VECTOR);
+            c.last_event_code = EVENT_SWI;
+            false
+        }
+        3 => { /* RETI */
+            // Back to the normal view with the pre-interrupt PSW (flags, I,
+            // SR/ER selections). Outside the shadow view there is nothing to
+            // return from, so only the flush happens.
+            if c.in_shadow() {
+                c.psw = c.spsw & !(1 << 5);
+            }
+            flush_pipeline(c);
+            c.nmi_active = false;
+            c.last_event_code = EVENT_RETI;
+            false
+        }
+        _ => { illegal(c, instr); false }
+    }
+}
+
+/// Applies the configured policy to an undefined or reserved encoding.
+fn illegal(c: &mut Cpu, instr: u16) {
+    match c.illegal_policy {
+        IllegalPolicy::Ignore => {}
+        IllegalPolicy::Halt => stop(c, StopReason::IllegalInstruction { addr: c.fetch_addr, instr }),
+        IllegalPolicy::Trap => {
+            enter_interrupt(c, ILLEGAL_VECTOR);
+            c.last_event_code = EVENT_TRAP;
+        }
+    }
+}
+
+/// Discards in-flight pipeline state. Every instruction is fetched from memory
+/// when it executes, so the only thing in flight is a pending branch: one whose
+/// delay slot holds RETI or FSH does not take effect.
+fn flush_pipeline(c: &mut Cpu) {
+    c.delay_active = false;
+    c.branch_taken = false;
+    pipeline::flush(c);
+}
+
+/// Interrupt entry shared by all interrupt sources (spec section 5.3).
+///
+/// The normal context is left untouched and becomes the return context; the
+/// shadow context starts in segment 0 with copies of DS/SS/ES, at the PC read
+/// from `vector`. PSW' keeps the pre-interrupt PSW for RETI. Entering from the
+/// shadow view (SWI or NMI inside a handler) abandons the running handler but
+/// keeps PSW', so RETI still returns to the normal context.
+pub(crate) fn enter_interrupt(c: &mut Cpu, vector: u16) {
+    pipeline::flush(c);
+    if !c.in_shadow() {
+        c.spsw = c.psw;
+    }
+    c.psw = (c.psw & !(1 << 4)) | (1 << 5);
+    c.scs = 0x0000;
+    c.sds = c.ds;
+    c.sss = c.ss;
+    c.ses = c.es;
+    let pa = phys(0, vector as u32);
+    c.spc = c.read_word(pa);
+    c.last_event_spc = c.spc;
+    c.last_event_scs = c.scs;
+}
@@ -0,0 +1,304 @@
+//! Breakpoint conditions: a small C-like expression language over machine
+//! state, parsed once and evaluated on every breakpoint hit.
+//!
+//! Operands are numbers (`42`, `0x2A`, `0b101010`), registers `R0`-`R15` and
+//! the aliases `FP`, `SP`, `LR`, `PC`, the flags `N Z V C I S` (0 or 1),
+//! `PSW`, the segment registers `CS DS SS ES`, and `[addr]`, the memory word
+//! at a physical address. Registers, PSW and segments are those of the active
+//! context. Operators, loosest first: `||`, `&&`, `== != < <= > >=`, `|`,
+//! `^`, `&`, `+ -`, and the unary `! ~ -`. Names are case-insensitive.
+//! Conditions nest at most `MAX_DEPTH` levels deep, counting parentheses,
+//! brackets, unary operators and each operator in a chain like `a + b + c`.
+//!
+//! `[]` does no segment arithmetic: with SS = 0x8000 the word at the top of
+//! the stack is `[0x80000 + SP]`.
+//!
+//! ```text
+//! R11 == 0x0234 && Z
+//! [0x80000 + SP] != 0 || !C
+//! ```
+
+use std::fmt;
+
+use crate::cpu::Cpu;
+
+/// A condition that failed to parse.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExprError {
+    /// Byte offset into the condition string.
+    pub pos: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "column {}: {}", self.pos + 1, self.msg)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum BinOp { Or, And, Eq, Ne, Lt, Le, Gt, Ge, BitOr, BitXor, BitAnd, Add, Sub }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum UnOp { Not, BitNot, Neg }
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Expr {
+    Num(i64),
+    Reg(usize),
+    /// PSW bit number.
+    Flag(u16),
+    Psw,
+    Seg(u16),
+    Mem(Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Tok {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPS: [&str; 20] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "~", "(", ")", "[", "]", "=",
+];
+
+fn tokenize(src: &str) -> Result<Vec<(usize, Tok)>, ExprError> {
+    let b = src.as_bytes();
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        let ch = b[i];
+        if ch.is_ascii_whitespace() { i += 1; continue; }
+        let start = i;
+        if ch.is_ascii_digit() {
+            while i < b.len() && b[i].is_ascii_alphanumeric() { i += 1; }
+            let text = &src[start..i];
+            let lower = text.to_ascii_lowercase();
+            let parsed = if let Some(h) = lower.strip_prefix("0x") {
+                i64::from_str_radix(h, 16)
+            } else if let Some(bin) = lower.strip_prefix("0b") {
+                i64::from_str_radix(bin, 2)
+            } else {
+                lower.parse()
+            };
+            let n = parsed.map_err(|_| ExprError { pos: start, msg: format!("bad number '{text}'") })?;
+            out.push((start, Tok::Num(n)));
+        } else if ch.is_ascii_alphabetic() || ch == b'_' {
+            while i < b.len() && (b[i].is_ascii_alphanumeric() || b[i] == b'_') { i += 1; }
+            out.push((start, Tok::Ident(src[start..i].to_ascii_uppercase())));
+        } else {
+            let Some(op) = OPS.iter().find(|op| src[i..].starts_with(**op)) else {
+                return Err(ExprError { pos: i, msg: format!("unexpected '{}'", &src[i..].chars().next().unwrap_or(' ')) });
+            };
+            if *op == "=" { return Err(ExprError { pos: i, msg: "use '==' to compare".into() }); }
+            i += op.len();
+            out.push((start, Tok::Op(op)));
+        }
+    }
+    Ok(out)
+}
+
+/// Deepest expression tree a condition may build. Parsing and evaluation
+/// recurse once per level, so this bounds their stack use.
+const MAX_DEPTH: usize = 64;
+
+struct Parser {
+    toks: Vec<(usize, Tok)>,
+    at: usize,
+    end: usize,
+    /// Nesting of the expression being parsed, see `MAX_DEPTH`.
+    depth: usize,
+}
+
+/// Binary operators by precedence level, loosest first.
+const LEVELS: [&[(&str, BinOp)]; 7] = [
+    &[("||", BinOp::Or)],
+    &[("&&", BinOp::And)],
+    &[("==", BinOp::Eq), ("!=", BinOp::Ne), ("<=", BinOp::Le), (">=", BinOp::Ge), ("<", BinOp::Lt), (">", BinOp::Gt)],
+    &[("|", BinOp::BitOr)],
+    &[("^", BinOp::BitXor)],
+    &[("&", BinOp::BitAnd)],
+    &[("+", BinOp::Add), ("-", BinOp::Sub)],
+];
+
+impl Parser {
+    fn pos(&self) -> usize {
+        self.toks.get(self.at).map_or(self.end, |t| t.0)
+    }
+
+    fn err<T>(&self, msg: impl Into<String>) -> Result<T, ExprError> {
+        Err(ExprError { pos: self.pos(), msg: msg.into() })
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.toks.get(self.at), Some((_, Tok::Op(o))) if *o == op) {
+            self.at += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ExprError> {
+        if self.eat(op) { Ok(()) } else { self.err(format!("expected '{op}'")) }
+    }
+
+    /// Goes one level deeper for the operator just eaten; the caller
+    /// restores `depth` on success.
+    fn nest(&mut self) -> Result<(), ExprError> {
+        self.depth += 1;
+        if self.depth <= MAX_DEPTH { return Ok(()); }
+        let pos = self.toks[self.at - 1].0;
+        Err(ExprError { pos, msg: format!("condition nested more than {MAX_DEPTH} levels deep") })
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ExprError> {
+        if level == LEVELS.len() { return self.unary(); }
+        let mut lhs = self.binary(level + 1)?;
+        // Chained operators build a left-leaning tree one level per operator
+        let outer = self.depth;
+        'outer: loop {
+            for &(op, bin) in LEVELS[level] {
+                if self.eat(op) {
+                    self.nest()?;
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(bin, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            self.depth = outer;
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        let op = if self.eat("!") {
+            UnOp::Not
+        } else if self.eat("~") {
+            UnOp::BitNot
+        } else if self.eat("-") {
+            UnOp::Neg
+        } else {
+            return self.primary();
+        };
+        self.nest()?;
+        let e = self.unary()?;
+        self.depth -= 1;
+        Ok(Expr::Unary(op, Box::new(e)))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat("(") {
+            self.nest()?;
+            let e = self.binary(0)?;
+            self.expect(")")?;
+            self.depth -= 1;
+            return Ok(e);
+        }
+        if self.eat("[") {
+            self.nest()?;
+            let e = self.binary(0)?;
+            self.expect("]")?;
+            self.depth -= 1;
+            return Ok(Expr::Mem(Box::new(e)));
+        }
+        let e = match self.toks.get(self.at) {
+            Some((_, Tok::Num(n))) => Expr::Num(*n),
+            Some((_, Tok::Ident(name))) => match name_to_expr(name) {
+                Some(e) => e,
+                None => return self.err(format!("unknown name '{name}'")),
+            },
+            Some(_) => return self.err("expected a value"),
+            None => return self.err("unexpected end of condition"),
+        };
+        self.at += 1;
+        Ok(e)
+    }
+}
+
+fn name_to_expr(name: &str) -> Option<Expr> {
+    let e = match name {
+        "FP" => Expr::Reg(12),
+        "SP" => Expr::Reg(13),
+        "LR" => Expr::Reg(14),
+        "PC" => Expr::Reg(15),
+        "N" => Expr::Flag(0),
+        "Z" => Expr::Flag(1),
+        "V" => Expr::Flag(2),
+        "C" => Expr::Flag(3),
+        "I" => Expr::Flag(4),
+        "S" => Expr::Flag(5),
+        "PSW" => Expr::Psw,
+        "CS" => Expr::Seg(0),
+        "DS" => Expr::Seg(1),
+        "SS" => Expr::Seg(2),
+        "ES" => Expr::Seg(3),
+        _ => {
+            let n: usize = name.strip_prefix('R')?.parse().ok()?;
+            if n > 15 { return None; }
+            Expr::Reg(n)
+        }
+    };
+    Some(e)
+}
+
+/// Parses a condition string.
+fn parse(src: &str) -> Result<Expr, ExprError> {
+    let mut p = Parser { toks: tokenize(src)?, at: 0, end: src.len(), depth: 0 };
+    if p.toks.is_empty() { return p.err("empty condition"); }
+    let e = p.binary(0)?;
+    if p.at < p.toks.len() { return p.err("unexpected trailing input"); }
+    Ok(e)
+}
+
+/// Parses an optional condition: blank means none.
+pub(crate) fn parse_condition(src: &str) -> Result<Option<Expr>, ExprError> {
+    if src.trim().is_empty() { Ok(None) } else { parse(src).map(Some) }
+}
+
+impl Expr {
+    pub(crate) fn eval(&self, c: &Cpu) -> i64 {
+        match self {
+            Expr::Num(n) => *n,
+            Expr::Reg(r) => c.registers()[*r] as i64,
+            Expr::Flag(bit) => ((c.psw >> bit) & 1) as i64,
+            Expr::Psw => c.psw as i64,
+            Expr::Seg(idx) => c.seg(*idx) as i64,
+            Expr::Mem(addr) => {
+                let a = addr.eval(c);
+                if a < 0 { 0xFFFF } else { c.read_word(a as usize) as i64 }
+            }
+            Expr::Unary(op, e) => {
+                let v = e.eval(c);
+                match op {
+                    UnOp::Not => (v == 0) as i64,
+                    UnOp::BitNot => !v & 0xFFFF,
+                    UnOp::Neg => v.wrapping_neg(),
+                }
+            }
+            Expr::Binary(BinOp::Or, a, b) => (a.eval(c) != 0 || b.eval(c) != 0) as i64,
+            Expr::Binary(BinOp::And, a, b) => (a.eval(c) != 0 && b.eval(c) != 0) as i64,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(c), b.eval(c));
+                match op {
+                    BinOp::Eq => (a == b) as i64,
+                    BinOp::Ne => (a != b) as i64,
+                    BinOp::Lt => (a < b) as i64,
+                    BinOp::Le => (a <= b) as i64,
+                    BinOp::Gt => (a > b) as i64,
+                    BinOp::Ge => (a >= b) as i64,
+                    BinOp::BitOr => a | b,
+                    BinOp::BitXor => a ^ b,
+                    BinOp::BitAnd => a & b,
+                    BinOp::Add => a.wrapping_add(b),
+                    BinOp::Sub => a.wrapping_sub(b),
+                    BinOp::Or | BinOp::And => unreachable!(),
+                }
+            }
+        }
+    }
+}
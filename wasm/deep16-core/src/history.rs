@@ -0,0 +1,214 @@
+//! Undo journal for stepping backwards.
+//!
+//! Every step that changes the machine (an instruction, including one in a
+//! delay slot, or an interrupt entry) appends one entry: the register-level
+//! state before the step and the old contents of every memory word it wrote.
+//! Steps that only stop the machine (a breakpoint, HLT, a fetch fault) leave
+//! no entry. The journal is bounded; the oldest entries are dropped first.
+//!
+//! Cycle counters and the pipeline view are not rewound, and neither are
+//! changes made from outside (`write_word`, `set_register`, ...), which a
+//! later `step_back` past them overwrites for registers and keeps for memory.
+
+use std::collections::VecDeque;
+
+use crate::cpu::Cpu;
+
+/// Entries kept unless `Cpu::set_history_limit` says otherwise.
+pub(crate) const DEFAULT_LIMIT: usize = 4096;
+
+/// Everything a step can change apart from memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct State {
+    reg: [u16; 16],
+    psw: u16,
+    spsw: u16,
+    cs: u16,
+    scs: u16,
+    spc: u16,
+    ds: u16,
+    ss: u16,
+    es: u16,
+    sds: u16,
+    sss: u16,
+    ses: u16,
+    delay_active: bool,
+    delayed_pc: u16,
+    delayed_cs: u16,
+    delayed_to_shadow: bool,
+    branch_taken: bool,
+    last_alu_result: i32,
+    last_op_alu: bool,
+    recent_addr: usize,
+    recent_base: u16,
+    recent_offset: u16,
+    recent_seg_val: u16,
+    recent_seg_idx: u16,
+    recent_is_store: bool,
+    last_event_code: u16,
+    last_event_spc: u16,
+    last_event_scs: u16,
+    irq_pending: bool,
+    nmi_pending: bool,
+    nmi_active: bool,
+}
+
+impl State {
+    fn capture(c: &Cpu) -> State {
+        State {
+            reg: c.reg,
+            psw: c.psw,
+            spsw: c.spsw,
+            cs: c.cs,
+            scs: c.scs,
+            spc: c.spc,
+            ds: c.ds,
+            ss: c.ss,
+            es: c.es,
+            sds: c.sds,
+            sss: c.sss,
+            ses: c.ses,
+            delay_active: c.delay_active,
+            delayed_pc: c.delayed_pc,
+            delayed_cs: c.delayed_cs,
+            delayed_to_shadow: c.delayed_to_shadow,
+            branch_taken: c.branch_taken,
+            last_alu_result: c.last_alu_result,
+            last_op_alu: c.last_op_alu,
+            recent_addr: c.recent_addr,
+            recent_base: c.recent_base,
+            recent_offset: c.recent_offset,
+            recent_seg_val: c.recent_seg_val,
+            recent_seg_idx: c.recent_seg_idx,
+            recent_is_store: c.recent_is_store,
+            last_event_code: c.last_event_code,
+            last_event_spc: c.last_event_spc,
+            last_event_scs: c.last_event_scs,
+            irq_pending: c.irq_pending,
+            nmi_pending: c.nmi_pending,
+            nmi_active: c.nmi_active,
+        }
+    }
+
+    fn restore(&self, c: &mut Cpu) {
+        c.reg = self.reg;
+        c.psw = self.psw;
+        c.spsw = self.spsw;
+        c.cs = self.cs;
+        c.scs = self.scs;
+        c.spc = self.spc;
+        c.ds = self.ds;
+        c.ss = self.ss;
+        c.es = self.es;
+        c.sds = self.sds;
+        c.sss = self.sss;
+        c.ses = self.ses;
+        c.delay_active = self.delay_active;
+        c.delayed_pc = self.delayed_pc;
+        c.delayed_cs = self.delayed_cs;
+        c.delayed_to_shadow = self.delayed_to_shadow;
+        c.branch_taken = self.branch_taken;
+        c.last_alu_result = self.last_alu_result;
+        c.last_op_alu = self.last_op_alu;
+        c.recent_addr = self.recent_addr;
+        c.recent_base = self.recent_base;
+        c.recent_offset = self.recent_offset;
+        c.recent_seg_val = self.recent_seg_val;
+        c.recent_seg_idx = self.recent_seg_idx;
+        c.recent_is_store = self.recent_is_store;
+        c.last_event_code = self.last_event_code;
+        c.last_event_spc = self.last_event_spc;
+        c.last_event_scs = self.last_event_scs;
+        c.irq_pending = self.irq_pending;
+        c.nmi_pending = self.nmi_pending;
+        c.nmi_active = self.nmi_active;
+    }
+}
+
+/// A memory word as it was before a step wrote it.
+#[derive(Clone, Copy, Debug)]
+struct OldWord {
+    addr: usize,
+    value: u16,
+    written: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    before: State,
+    mem: Vec<OldWord>,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct History {
+    pub(crate) limit: usize,
+    entries: VecDeque<Entry>,
+    /// The step in progress, while `step_one` runs.
+    open: Option<Entry>,
+}
+
+impl Default for History {
+    fn default() -> History {
+        History { limit: DEFAULT_LIMIT, entries: VecDeque::new(), open: None }
+    }
+}
+
+impl History {
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.open = None;
+    }
+
+    pub(crate) fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        while self.entries.len() > limit { self.entries.pop_front(); }
+    }
+}
+
+/// Opens a journal entry for the step about to run.
+pub(crate) fn begin(c: &mut Cpu) {
+    if c.history.limit == 0 { return; }
+    c.history.open = Some(Entry { before: State::capture(c), mem: Vec::new() });
+}
+
+/// Records the word at `addr` before the current step overwrites it.
+pub(crate) fn note_write(c: &mut Cpu, addr: usize) {
+    let (value, written) = (c.mem[addr], c.is_written(addr));
+    if let Some(e) = c.history.open.as_mut() { e.mem.push(OldWord { addr, value, written }); }
+}
+
+/// Closes the current entry, keeping it if the step changed anything.
+pub(crate) fn commit(c: &mut Cpu) {
+    let Some(e) = c.history.open.take() else { return; };
+    if e.mem.is_empty() && e.before == State::capture(c) { return; }
+    let h = &mut c.history;
+    if h.entries.len() == h.limit { h.entries.pop_front(); }
+    h.entries.push_back(e);
+}
+
+/// Undoes up to `n` steps, newest first, and returns how many were undone.
+/// The machine is left stopped with no stop reason.
+pub(crate) fn step_back(c: &mut Cpu, n: u32) -> u32 {
+    let mut undone = 0;
+    while undone < n {
+        let Some(e) = c.history.entries.pop_back() else { break; };
+        for w in e.mem.iter().rev() {
+            c.mem[w.addr] = w.value;
+            let bit = 1u64 << (w.addr % 64);
+            if w.written { c.written[w.addr / 64] |= bit; } else { c.written[w.addr / 64] &= !bit; }
+        }
+        e.before.restore(c);
+        undone += 1;
+    }
+    if undone > 0 {
+        c.running = false;
+        c.stop_reason = None;
+        c.debug.resume_addr = None;
+        c.debug.watch_hit = None;
+    }
+    undone
+}
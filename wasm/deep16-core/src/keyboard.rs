@@ -0,0 +1,113 @@
+//! Keyboard controller at 0xF0060 (spec section 10.2.6).
+//!
+//! | Address | Register |
+//! |---------|----------|
+//! | 0xF0060 | Status: bit0 data ready, bit1 FIFO overflowed (cleared by reading status) |
+//! | 0xF0062 | Data: reading pops the oldest key code, 0 when empty |
+//! | 0xF0064 | Control: bit0 interrupt enable; writing bit1 empties the FIFO |
+//!
+//! Key codes come from the host through `Keyboard::push_key`. With the
+//! interrupt enabled the controller holds the interrupt line high while the
+//! FIFO has data, so a handler reads keys until status bit 0 clears.
+
+use std::collections::VecDeque;
+
+use crate::bus::Device;
+
+pub const KEYBOARD_BASE: usize = 0xF0060;
+/// Words of I/O space the controller decodes.
+pub const KEYBOARD_LEN: usize = 0x10;
+/// Key codes the FIFO holds before further keys are dropped.
+pub const KEY_FIFO_LEN: usize = 16;
+
+const STATUS: usize = 0;
+const DATA: usize = 2;
+const CONTROL: usize = 4;
+
+const STATUS_READY: u16 = 1 << 0;
+const STATUS_OVERFLOW: u16 = 1 << 1;
+const CONTROL_IRQ: u16 = 1 << 0;
+const CONTROL_FLUSH: u16 = 1 << 1;
+
+#[derive(Clone, Debug, Default)]
+pub struct Keyboard {
+    fifo: VecDeque<u16>,
+    overflow: bool,
+    control: u16,
+}
+
+impl Keyboard {
+    /// Queues a key code; returns `false` if the FIFO was full and the key
+    /// was dropped.
+    pub fn push_key(&mut self, code: u16) -> bool {
+        if self.fifo.len() == KEY_FIFO_LEN {
+            self.overflow = true;
+            return false;
+        }
+        self.fifo.push_back(code);
+        true
+    }
+
+    /// Key codes waiting to be read.
+    pub fn pending(&self) -> usize {
+        self.fifo.len()
+    }
+
+    fn status(&self) -> u16 {
+        let mut s = 0;
+        if !self.fifo.is_empty() { s |= STATUS_READY; }
+        if self.overflow { s |= STATUS_OVERFLOW; }
+        s
+    }
+}
+
+impl Device for Keyboard {
+    fn read(&mut self, offset: usize) -> u16 {
+        match offset {
+            STATUS => {
+                let s = self.status();
+                self.overflow = false;
+                s
+            }
+            DATA => self.fifo.pop_front().unwrap_or(0),
+            _ => self.peek(offset),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        match offset {
+            STATUS => self.status(),
+            DATA => self.fifo.front().copied().unwrap_or(0),
+            CONTROL => self.control,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16) {
+        if offset != CONTROL { return; }
+        if value & CONTROL_FLUSH != 0 { self.fifo.clear(); }
+        self.control = value & CONTROL_IRQ;
+    }
+
+    fn irq(&self) -> bool {
+        self.control & CONTROL_IRQ != 0 && !self.fifo.is_empty()
+    }
+
+    fn reset(&mut self) {
+        *self = Keyboard::default();
+    }
+
+    /// Control, overflow flag, then the queued key codes.
+    fn save(&self) -> Vec<u8> {
+        let head = [self.control, self.overflow as u16];
+        head.iter().chain(&self.fifo).flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        let mut words = data.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        *self = Keyboard::default();
+        self.control = words.next().unwrap_or(0) & CONTROL_IRQ;
+        self.overflow = words.next().unwrap_or(0) != 0;
+        self.fifo.extend(words.take(KEY_FIFO_LEN));
+    }
+}
@@ -0,0 +1,43 @@
+//! Deep16 CPU model: instruction decode and execution, memory and the
+//! register file, without any dependency on a browser or wasm-bindgen.
+//!
+//! ```
+//! let mut cpu = deep16_core::Cpu::new(1 << 20);
+//! cpu.load_program(0x0100, &[0x002A, 0xFFFF]); // LDI 42; HLT
+//! cpu.run(1000);
+//! assert_eq!(cpu.registers()[0], 42);
+//! ```
+
+mod bus;
+mod cpu;
+mod debug;
+mod disasm;
+mod exec;
+mod expr;
+mod history;
+mod keyboard;
+mod pipeline;
+mod screen;
+mod snapshot;
+mod timer;
+mod trace;
+mod uart;
+
+pub use bus::Device;
+pub use cpu::{
+    Cpu, IllegalPolicy, MemAccess, StopReason, EVENT_IRQ, EVENT_NMI, EVENT_RETI, EVENT_SWI, EVENT_TRAP, HW_INT_VECTOR,
+    ILLEGAL_VECTOR, NMI_VECTOR, RESET_VECTOR, SWI_VECTOR,
+};
+pub use debug::{BreakAt, WatchHit, WatchKind};
+pub use disasm::{disassemble, disassemble_at, InstrClass};
+pub use expr::ExprError;
+pub use keyboard::{Keyboard, KEYBOARD_BASE, KEYBOARD_LEN, KEY_FIFO_LEN};
+pub use pipeline::{CycleCounters, SlotKind, StageSlot, EX, ID, IF, MEM, WB};
+pub use screen::{
+    CellStyle, DirtySpan, Screen, VideoController, ATTR_BLINK, ATTR_INVERSE, SCREEN_BASE, SCREEN_COLS, SCREEN_LEN,
+    SCREEN_ROWS, VIDEO_BASE, VIDEO_BLINK, VIDEO_CURSOR_ON, VIDEO_LEN,
+};
+pub use snapshot::SnapshotError;
+pub use timer::{Timer, TIMER_BASE, TIMER_EXPIRED, TIMER_IRQ, TIMER_LEN, TIMER_LOAD, TIMER_PERIODIC, TIMER_RUN};
+pub use trace::{trace_to_binary, trace_to_text, RegDelta, TraceEntry, TraceFilter, TraceMem};
+pub use uart::{Uart, UART_BASE, UART_LEN, UART_RX_READY, UART_TX_READY};
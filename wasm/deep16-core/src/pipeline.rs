@@ -0,0 +1,310 @@
+//! Optional timing model for the 5-stage pipeline (spec section 7).
+//!
+//! Execution itself stays instruction-level in `exec`; when timing is enabled
+//! each executed instruction is charged its cost in clocks on top of that:
+//!
+//! - one issue cycle per instruction, plus the pipeline fill (4 cycles) before
+//!   the first one completes;
+//! - one stall cycle when an instruction needs the result of the load right
+//!   before it (LD, LDS, or MVS reading a segment, all of which deliver in
+//!   MEM and cannot be forwarded to EX in time);
+//! - one stall cycle for `MOV Rd, Rs, 3` (AMV/ALNK), which reads the register
+//!   file instead of the forwarding paths;
+//! - two bubbles for every flush (RETI, FSH, JML and interrupt entry), for
+//!   the instructions squashed in IF and ID.
+//!
+//! Taken and untaken branches cost nothing extra thanks to the delay slot.
+//!
+//! `step_cycle` replays the same costs one clock at a time as IF/ID/EX/MEM/WB
+//! occupancy. Instructions still execute whole when they are fetched, so the
+//! registers and memory always reflect the instruction in IF; the stages show
+//! where each instruction would be in the hardware.
+
+use crate::cpu::Cpu;
+use crate::exec::step_one;
+
+/// Cycles before the first instruction reaches WB.
+const FILL_CYCLES: u64 = 4;
+/// Slots squashed by a flush.
+const FLUSH_BUBBLES: u64 = 2;
+
+/// Running totals of the timing model.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CycleCounters {
+    /// Clock cycles, including the initial pipeline fill.
+    pub cycles: u64,
+    /// Instructions executed.
+    pub instructions: u64,
+    /// Cycles the front end was held for a load-use or AMV hazard.
+    pub stalls: u64,
+    /// Empty slots left behind by flushes.
+    pub bubbles: u64,
+    /// Pipeline flushes.
+    pub flushes: u64,
+    /// Cycles charged to the most recent step.
+    pub last_step_cycles: u64,
+}
+
+/// What occupies a pipeline stage.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SlotKind {
+    #[default]
+    Empty,
+    Instr,
+    /// Bubble inserted while the instruction in ID waits on a hazard.
+    Stall,
+    /// Bubble left by a flush.
+    Flush,
+}
+
+/// One pipeline stage on the current clock.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageSlot {
+    pub kind: SlotKind,
+    pub instr: u16,
+    pub pc: u16,
+    pub cs: u16,
+    /// Physical data address of a LD/ST/LDS/STS.
+    pub mem_addr: Option<usize>,
+    /// In EX: an operand comes over the EX/MEM -> EX forwarding path.
+    pub fwd_from_mem: bool,
+    /// In EX: an operand comes over the MEM/WB -> EX forwarding path.
+    pub fwd_from_wb: bool,
+    /// In ID: this instruction is held for one stall cycle.
+    pub(crate) stall_pending: bool,
+}
+
+/// Stage indices into `Cpu::pipeline_stages`.
+pub const IF: usize = 0;
+pub const ID: usize = 1;
+pub const EX: usize = 2;
+pub const MEM: usize = 3;
+pub const WB: usize = 4;
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CycleView {
+    pub(crate) stages: [StageSlot; 5],
+    /// Flush bubbles still to be fetched.
+    pending_flush: u8,
+    /// JML: the flush bubbles follow its delay slot.
+    flush_after_slot: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Timing {
+    pub(crate) enabled: bool,
+    pub(crate) counters: CycleCounters,
+    /// Register the previous instruction loads in MEM, if any.
+    pub(crate) load_dest: Option<usize>,
+    pub(crate) view: CycleView,
+}
+
+impl Timing {
+    /// Fresh counters, keeping whether timing is enabled.
+    pub(crate) fn cleared(&self) -> Timing {
+        Timing { enabled: self.enabled, ..Timing::default() }
+    }
+
+    fn charge(&mut self, cycles: u64) {
+        self.counters.cycles += cycles;
+        self.counters.last_step_cycles += cycles;
+    }
+}
+
+pub(crate) fn begin_step(c: &mut Cpu) {
+    if c.timing.enabled { c.timing.counters.last_step_cycles = 0; }
+}
+
+/// Charges `instr`, about to execute, for its issue slot and any stall.
+pub(crate) fn issue(c: &mut Cpu, instr: u16) {
+    let t = &mut c.timing;
+    if !t.enabled { return; }
+    let mut cycles = 1;
+    if t.counters.cycles == 0 { cycles += FILL_CYCLES; }
+    let load_use = t.load_dest.is_some_and(|r| sources(instr) & (1 << r) != 0);
+    if load_use || is_amv(instr) {
+        t.counters.stalls += 1;
+        cycles += 1;
+    }
+    t.counters.instructions += 1;
+    t.load_dest = load_dest(instr);
+    t.charge(cycles);
+}
+
+/// Charges a pipeline flush.
+pub(crate) fn flush(c: &mut Cpu) {
+    let t = &mut c.timing;
+    if !t.enabled { return; }
+    t.counters.flushes += 1;
+    t.counters.bubbles += FLUSH_BUBBLES;
+    t.load_dest = None;
+    t.charge(FLUSH_BUBBLES);
+}
+
+/// Advances the pipeline by one clock. Turns the timing model on, and
+/// returns `false` once the machine has stopped and the pipeline has drained.
+pub(crate) fn step_cycle(c: &mut Cpu) -> bool {
+    c.timing.enabled = true;
+    if c.timing.view.stages[ID].stall_pending {
+        // IF and ID hold, a bubble enters EX
+        let s = &mut c.timing.view.stages;
+        s[ID].stall_pending = false;
+        s[WB] = s[MEM];
+        s[MEM] = s[EX];
+        s[EX] = StageSlot { kind: SlotKind::Stall, ..StageSlot::default() };
+    } else {
+        let fetched = fetch_slot(c);
+        let s = &mut c.timing.view.stages;
+        s.copy_within(IF..WB, ID);
+        s[IF] = fetched;
+    }
+    mark_forwarding(&mut c.timing.view.stages);
+    c.running || c.timing.view.stages.iter().any(|s| s.kind != SlotKind::Empty)
+}
+
+/// What enters IF this clock: a flush bubble, the next instruction (which is
+/// executed now), or nothing once the machine has stopped.
+fn fetch_slot(c: &mut Cpu) -> StageSlot {
+    let v = &mut c.timing.view;
+    if v.pending_flush > 0 && !v.flush_after_slot {
+        v.pending_flush -= 1;
+        return StageSlot { kind: SlotKind::Flush, ..StageSlot::default() };
+    }
+    if !c.running && c.stop_reason.is_some() { return StageSlot::default(); }
+    let after_slot = v.flush_after_slot;
+    let pc = if c.in_shadow() { c.spc } else { c.reg[15] };
+    let cs = c.seg(0);
+    let before = c.timing.counters;
+    step_one(c);
+    let after = c.timing.counters;
+    let instr = c.fetch_instr;
+    let v = &mut c.timing.view;
+    if after_slot { v.flush_after_slot = false; }
+    let mut slot = StageSlot::default();
+    if after.instructions > before.instructions {
+        let is_mem = matches!(instr.leading_ones(), 1 | 4);
+        slot = StageSlot {
+            kind: SlotKind::Instr,
+            instr,
+            pc,
+            cs,
+            mem_addr: if is_mem { Some(c.recent_addr) } else { None },
+            stall_pending: after.stalls > before.stalls,
+            ..StageSlot::default()
+        };
+    }
+    if after.flushes > before.flushes {
+        v.pending_flush += FLUSH_BUBBLES as u8;
+        // A JML still has its delay slot to fetch
+        if c.delay_active { v.flush_after_slot = true; }
+        // Interrupt entry has no instruction of its own: its first bubble
+        // takes this slot
+        if slot.kind == SlotKind::Empty {
+            v.pending_flush -= 1;
+            slot.kind = SlotKind::Flush;
+        }
+    }
+    slot
+}
+
+/// Sets the forwarding flags of the instruction now in EX. A load in MEM
+/// cannot forward (that case stalls instead) and AMV reads the register file.
+fn mark_forwarding(s: &mut [StageSlot; 5]) {
+    let ex = s[EX];
+    let (mut from_mem, mut from_wb) = (false, false);
+    if ex.kind == SlotKind::Instr && !is_amv(ex.instr) {
+        let src = sources(ex.instr);
+        let writes = |slot: &StageSlot| slot.kind == SlotKind::Instr && dests(slot.instr) & src != 0;
+        from_mem = writes(&s[MEM]) && load_dest(s[MEM].instr).is_none();
+        from_wb = writes(&s[WB]);
+    }
+    for slot in s.iter_mut() {
+        slot.fwd_from_mem = false;
+        slot.fwd_from_wb = false;
+    }
+    s[EX].fwd_from_mem = from_mem;
+    s[EX].fwd_from_wb = from_wb;
+}
+
+/// `MOV Rd, Rs, 3`, which bypasses forwarding.
+pub(crate) fn is_amv(instr: u16) -> bool {
+    instr.leading_ones() == 5 && (instr & 0x3) == 3
+}
+
+/// Bit mask of the registers `instr` reads. The major opcode is the number of
+/// leading one bits (spec Table D).
+pub(crate) fn sources(instr: u16) -> u32 {
+    let bit = |r: u16| 1u32 << (r & 0xF);
+    match instr.leading_ones() {
+        // LD/ST: base register, plus the data register for ST
+        1 => {
+            let rb = bit(instr >> 5);
+            if (instr >> 13) & 1 == 1 { rb | bit(instr >> 9) } else { rb }
+        }
+        // ALU: Rd is always read, Rs in the register forms
+        2 => {
+            let func5 = (instr >> 8) & 0x1F;
+            let rd = (instr >> 4) & 0xF;
+            let is_reg = (func5 < 0b10000 && func5 & 1 == 0) || func5 >= 0b11100;
+            let mut m = bit(rd);
+            if is_reg { m |= bit(instr); }
+            if func5 == 0b11111 { m |= 1 << (rd + 1); }
+            m
+        }
+        // LDS/STS: address register, plus the data register for STS
+        4 => {
+            let rs = bit(instr);
+            if (instr >> 10) & 1 == 1 { rs | bit(instr >> 4) } else { rs }
+        }
+        // MOV
+        5 => bit(instr >> 2),
+        // SOP: SWB/INV/NEG read Rx, JML reads the pair
+        7 => match (instr >> 4) & 0xF {
+            0b0000..=0b0010 => bit(instr),
+            0b0100 => bit(instr) | (1 << ((instr & 0xF) + 1)),
+            _ => 0,
+        },
+        // MVS seg, Rd
+        8 if (instr >> 6) & 1 == 1 => bit(instr >> 2),
+        // SMV alt, R0
+        10 if (instr >> 4) & 1 == 0 => bit(0),
+        _ => 0,
+    }
+}
+
+/// Bit mask of the general registers `instr` writes.
+pub(crate) fn dests(instr: u16) -> u32 {
+    let bit = |r: u16| 1u32 << (r & 0xF);
+    match instr.leading_ones() {
+        0 => bit(0),
+        1 if (instr >> 13) & 1 == 0 => bit(instr >> 9),
+        2 => {
+            let func5 = (instr >> 8) & 0x1F;
+            let rd = (instr >> 4) & 0xF;
+            match func5 {
+                // CMP, TBC, TBS only set flags
+                0b00100 | 0b00101 | 0b01000 | 0b01001 | 0b01110 | 0b01111 => 0,
+                0b11101..=0b11111 => bit(rd) | (1 << (rd + 1)),
+                _ => bit(rd),
+            }
+        }
+        4 if (instr >> 10) & 1 == 0 => bit(instr >> 4),
+        5 => bit(instr >> 6),
+        6 => bit(instr >> 5),
+        7 if (instr >> 4) & 0xF <= 0b0010 => bit(instr),
+        8 if (instr >> 6) & 1 == 0 => bit(instr >> 2),
+        10 if (instr >> 4) & 1 == 1 => bit(0),
+        11 => bit(instr),
+        _ => 0,
+    }
+}
+
+/// Register loaded in MEM by `instr`, if it is LD, LDS or MVS Rd, seg.
+pub(crate) fn load_dest(instr: u16) -> Option<usize> {
+    match instr.leading_ones() {
+        1 if (instr >> 13) & 1 == 0 => Some(((instr >> 9) & 0xF) as usize),
+        4 if (instr >> 10) & 1 == 0 => Some(((instr >> 4) & 0xF) as usize),
+        8 if (instr >> 6) & 1 == 0 => Some(((instr >> 2) & 0xF) as usize),
+        _ => None,
+    }
+}
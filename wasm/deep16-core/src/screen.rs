@@ -0,0 +1,254 @@
+//! Text screen: the video controller at 0xF0030 (spec section 10.2.4) and
+//! the 80x25 screen buffer at 0xF1000 (section 6.3).
+//!
+//! | Address | Register |
+//! |---------|----------|
+//! | 0xF0030 | Control: bit0 cursor visible, bit1 blinking enabled |
+//! | 0xF0032 | Cursor column, 0-79 |
+//! | 0xF0034 | Cursor row, 0-24 |
+//! | 0xF0036 | Default attributes for cells whose attribute byte is 0 |
+//!
+//! Each screen cell holds the character in its low byte and attributes in
+//! its high byte: bits 0-2 foreground colour, bits 3-5 background colour,
+//! bit 6 inverse (swap the two), bit 7 blink. Colours are numbered black,
+//! blue, green, cyan, red, magenta, brown, white. A cell with attribute byte
+//! 0 is drawn with the default attributes, so programs that store plain
+//! character codes get white on black after reset.
+//!
+//! The screen buffer remembers which cells have changed since the host last
+//! asked (`Screen::take_dirty`), so a display only has to redraw those.
+
+use crate::bus::Device;
+
+pub const VIDEO_BASE: usize = 0xF0030;
+/// Words of I/O space the video controller decodes.
+pub const VIDEO_LEN: usize = 0x10;
+pub const SCREEN_BASE: usize = 0xF1000;
+pub const SCREEN_COLS: usize = 80;
+pub const SCREEN_ROWS: usize = 25;
+pub const SCREEN_LEN: usize = SCREEN_COLS * SCREEN_ROWS;
+
+pub const VIDEO_CURSOR_ON: u16 = 1 << 0;
+pub const VIDEO_BLINK: u16 = 1 << 1;
+pub const ATTR_INVERSE: u8 = 1 << 6;
+pub const ATTR_BLINK: u8 = 1 << 7;
+
+const CONTROL: usize = 0;
+const CURSOR_X: usize = 2;
+const CURSOR_Y: usize = 4;
+const ATTRIBUTES: usize = 6;
+
+const CONTROL_BITS: u16 = VIDEO_CURSOR_ON | VIDEO_BLINK;
+const RESET_CONTROL: u16 = VIDEO_CURSOR_ON | VIDEO_BLINK;
+/// White on black.
+const RESET_ATTRIBUTES: u8 = 0x07;
+
+/// How a cell is drawn, after applying default attributes and inverse.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CellStyle {
+    pub ch: u8,
+    pub fg: u8,
+    pub bg: u8,
+    /// Set only while the controller has blinking enabled.
+    pub blink: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VideoController {
+    control: u16,
+    cursor_x: u16,
+    cursor_y: u16,
+    attributes: u8,
+}
+
+impl Default for VideoController {
+    fn default() -> Self {
+        VideoController { control: RESET_CONTROL, cursor_x: 0, cursor_y: 0, attributes: RESET_ATTRIBUTES }
+    }
+}
+
+impl VideoController {
+    pub fn control(&self) -> u16 {
+        self.control
+    }
+
+    /// `(column, row)`.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_x as usize, self.cursor_y as usize)
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.control & VIDEO_CURSOR_ON != 0
+    }
+
+    pub fn attributes(&self) -> u8 {
+        self.attributes
+    }
+
+    /// Resolves the colours of screen cell `cell`.
+    pub fn style(&self, cell: u16) -> CellStyle {
+        let attr = match (cell >> 8) as u8 {
+            0 => self.attributes,
+            a => a,
+        };
+        let (mut fg, mut bg) = (attr & 7, (attr >> 3) & 7);
+        if attr & ATTR_INVERSE != 0 { std::mem::swap(&mut fg, &mut bg); }
+        let blink = attr & ATTR_BLINK != 0 && self.control & VIDEO_BLINK != 0;
+        CellStyle { ch: cell as u8, fg, bg, blink }
+    }
+}
+
+impl Device for VideoController {
+    fn read(&mut self, offset: usize) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        match offset {
+            CONTROL => self.control,
+            CURSOR_X => self.cursor_x,
+            CURSOR_Y => self.cursor_y,
+            ATTRIBUTES => self.attributes as u16,
+            _ => 0,
+        }
+    }
+
+    /// Cursor positions past the edge of the screen stop at the last column
+    /// or row.
+    fn write(&mut self, offset: usize, value: u16) {
+        match offset {
+            CONTROL => self.control = value & CONTROL_BITS,
+            CURSOR_X => self.cursor_x = value.min(SCREEN_COLS as u16 - 1),
+            CURSOR_Y => self.cursor_y = value.min(SCREEN_ROWS as u16 - 1),
+            ATTRIBUTES => self.attributes = value as u8,
+            _ => {}
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = VideoController::default();
+    }
+
+    /// Control, cursor column, cursor row and default attributes as `u16`.
+    fn save(&self) -> Vec<u8> {
+        [self.control, self.cursor_x, self.cursor_y, self.attributes as u16].iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        self.reset();
+        for (i, b) in data.chunks_exact(2).take(4).enumerate() {
+            self.write(i * 2, u16::from_le_bytes([b[0], b[1]]));
+        }
+    }
+}
+
+/// Changed cells `start..end` of one screen row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtySpan {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screen {
+    cells: Vec<u16>,
+    /// Changed columns of each row, `start..end`, empty if none.
+    dirty: [(u8, u8); SCREEN_ROWS],
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen { cells: vec![0; SCREEN_LEN], dirty: [(0, SCREEN_COLS as u8); SCREEN_ROWS] }
+    }
+}
+
+impl Screen {
+    /// All cells, row by row.
+    pub fn cells(&self) -> &[u16] {
+        &self.cells
+    }
+
+    /// The cell at `col`, `row`, or `None` past the last column or row.
+    pub fn cell(&self, col: usize, row: usize) -> Option<u16> {
+        if col >= SCREEN_COLS || row >= SCREEN_ROWS { return None; }
+        Some(self.cells[row * SCREEN_COLS + col])
+    }
+
+    /// One row as text, without trailing blanks, or `None` past the last
+    /// row. Cells holding 0 or a line break show as spaces, other
+    /// unprintable characters as '·'.
+    pub fn row_text(&self, row: usize) -> Option<String> {
+        if row >= SCREEN_ROWS { return None; }
+        let cells = &self.cells[row * SCREEN_COLS..(row + 1) * SCREEN_COLS];
+        let text: String = cells
+            .iter()
+            .map(|&c| match c as u8 {
+                b @ 0x20..=0x7E => b as char,
+                0 | b'\n' | b'\r' => ' ',
+                _ => '·',
+            })
+            .collect();
+        Some(text.trim_end().to_string())
+    }
+
+    /// The whole screen as text, one line per row as `row_text` gives it.
+    pub fn text(&self) -> String {
+        (0..SCREEN_ROWS).filter_map(|r| self.row_text(r)).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Returns the cells changed since the last call, one span per row with
+    /// changes, and forgets them. After reset or a snapshot load the whole
+    /// screen counts as changed.
+    pub fn take_dirty(&mut self) -> Vec<DirtySpan> {
+        let mut spans = Vec::new();
+        for (row, d) in self.dirty.iter_mut().enumerate() {
+            if d.0 < d.1 { spans.push(DirtySpan { row, start: d.0 as usize, end: d.1 as usize }); }
+            *d = (0, 0);
+        }
+        spans
+    }
+
+    /// Marks every cell as changed, for a display that has lost its contents.
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = [(0, SCREEN_COLS as u8); SCREEN_ROWS];
+    }
+}
+
+impl Device for Screen {
+    fn read(&mut self, offset: usize) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        self.cells.get(offset).copied().unwrap_or(0)
+    }
+
+    /// Storing the value a cell already holds does not mark it changed.
+    fn write(&mut self, offset: usize, value: u16) {
+        let Some(cell) = self.cells.get_mut(offset) else { return; };
+        if *cell == value { return; }
+        *cell = value;
+        let (row, col) = (offset / SCREEN_COLS, (offset % SCREEN_COLS) as u8);
+        let d = &mut self.dirty[row];
+        *d = if d.0 < d.1 { (d.0.min(col), d.1.max(col + 1)) } else { (col, col + 1) };
+    }
+
+    fn reset(&mut self) {
+        *self = Screen::default();
+    }
+
+    /// The cells as `u16`, row by row, leaving out trailing zero cells; a
+    /// blank screen saves nothing.
+    fn save(&self) -> Vec<u8> {
+        let used = self.cells.iter().rposition(|&c| c != 0).map_or(0, |i| i + 1);
+        self.cells[..used].iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        self.reset();
+        for (cell, b) in self.cells.iter_mut().zip(data.chunks_exact(2)) {
+            *cell = u16::from_le_bytes([b[0], b[1]]);
+        }
+    }
+}
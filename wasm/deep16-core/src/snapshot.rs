@@ -0,0 +1,294 @@
+//! Whole-machine snapshots: `Cpu::save_state` / `Cpu::load_state`.
+//!
+//! The format (doc/Deep16-Snapshot.md) is a small header followed by tagged,
+//! length-prefixed chunks, all little endian:
+//!
+//! ```text
+//! "D16S"  u16 version  u16 reserved
+//! tag[4]  u32 length   payload[length]      repeated, ending with "END "
+//! ```
+//!
+//! Version 1 writes `CPU ` (registers, segments, shadow state, PSW, delay
+//! latches and interrupt state), `MEM ` (run-length compressed memory),
+//! `INIT` (which words have been written) and a `DEV ` chunk per mapped
+//! device with state (its start address, then `Device::save`). Readers skip
+//! chunks they do not know and ignore bytes past the fields they do know at
+//! the end of a chunk, so later versions can add both.
+
+use std::fmt;
+
+use crate::cpu::Cpu;
+
+const MAGIC: &[u8; 4] = b"D16S";
+const VERSION: u16 = 1;
+
+const TAG_CPU: &[u8; 4] = b"CPU ";
+const TAG_MEM: &[u8; 4] = b"MEM ";
+const TAG_INIT: &[u8; 4] = b"INIT";
+const TAG_DEV: &[u8; 4] = b"DEV ";
+const TAG_END: &[u8; 4] = b"END ";
+
+/// Longest run or literal block one RLE header can describe.
+const MAX_BLOCK: usize = 0x8000;
+
+/// Why a snapshot could not be loaded. The machine is left untouched.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// The data does not start with `D16S`.
+    BadMagic,
+    /// Written by a newer, incompatible format version.
+    UnsupportedVersion(u16),
+    /// The data ends in the middle of a header or chunk.
+    Truncated,
+    /// A required chunk is absent.
+    MissingChunk(&'static str),
+    /// A chunk is present but its contents do not add up.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SnapshotError::BadMagic => write!(f, "not a Deep16 snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {v}"),
+            SnapshotError::Truncated => write!(f, "snapshot is truncated"),
+            SnapshotError::MissingChunk(tag) => write!(f, "snapshot has no {tag} chunk"),
+            SnapshotError::Corrupt(what) => write!(f, "corrupt snapshot: {what}"),
+        }
+    }
+}
+
+fn put_u16(out: &mut Vec<u8>, v: u16) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_u32(out: &mut Vec<u8>, v: u32) {
+    out.extend_from_slice(&v.to_le_bytes());
+}
+
+fn put_chunk(out: &mut Vec<u8>, tag: &[u8; 4], payload: &[u8]) {
+    out.extend_from_slice(tag);
+    put_u32(out, payload.len() as u32);
+    out.extend_from_slice(payload);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], SnapshotError> {
+        let end = self.at.checked_add(n).filter(|&e| e <= self.data.len()).ok_or(SnapshotError::Truncated)?;
+        let b = &self.data[self.at..end];
+        self.at = end;
+        Ok(b)
+    }
+
+    fn u16(&mut self) -> Result<u16, SnapshotError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, SnapshotError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.at == self.data.len()
+    }
+}
+
+/// Number of words equal to `words[i]` starting at `i`, up to `MAX_BLOCK`.
+fn run_len(words: &[u16], i: usize) -> usize {
+    words[i..].iter().take(MAX_BLOCK).take_while(|&&w| w == words[i]).count()
+}
+
+/// Header word `0x8000 | (n-1)` repeats the next word n times; `n-1` is
+/// followed by n literal words. Runs shorter than 3 go into literal blocks.
+fn rle_encode(words: &[u16], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < words.len() {
+        let run = run_len(words, i);
+        if run >= 3 {
+            put_u16(out, 0x8000 | (run - 1) as u16);
+            put_u16(out, words[i]);
+            i += run;
+            continue;
+        }
+        let start = i;
+        while i < words.len() && i - start < MAX_BLOCK && run_len(words, i) < 3 { i += 1; }
+        put_u16(out, (i - start - 1) as u16);
+        for &w in &words[start..i] { put_u16(out, w); }
+    }
+}
+
+fn rle_decode(r: &mut Reader, len: usize) -> Result<Vec<u16>, SnapshotError> {
+    let mut words = Vec::with_capacity(len.min(1 << 21));
+    while words.len() < len {
+        let h = r.u16()?;
+        let n = (h & 0x7FFF) as usize + 1;
+        if words.len() + n > len { return Err(SnapshotError::Corrupt("memory runs overflow")); }
+        if h & 0x8000 != 0 {
+            let v = r.u16()?;
+            words.resize(words.len() + n, v);
+        } else {
+            for _ in 0..n { words.push(r.u16()?); }
+        }
+    }
+    Ok(words)
+}
+
+/// Encodes the machine state.
+pub(crate) fn save(c: &Cpu) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    put_u16(&mut out, VERSION);
+    put_u16(&mut out, 0);
+
+    let mut p = Vec::new();
+    for r in c.reg { put_u16(&mut p, r); }
+    for v in [c.psw, c.spsw, c.cs, c.ds, c.ss, c.es, c.scs, c.spc, c.sds, c.sss, c.ses, c.delayed_pc, c.delayed_cs] {
+        put_u16(&mut p, v);
+    }
+    let flags = [
+        c.delay_active,
+        c.delayed_to_shadow,
+        c.branch_taken,
+        c.last_op_alu,
+        c.irq_pending,
+        c.irq_line,
+        c.nmi_pending,
+        c.nmi_active,
+        c.recent_is_store,
+    ];
+    put_u16(&mut p, flags.iter().enumerate().fold(0, |acc, (i, &f)| acc | ((f as u16) << i)));
+    put_u32(&mut p, c.last_alu_result as u32);
+    put_u32(&mut p, c.recent_addr as u32);
+    for v in [c.recent_base, c.recent_offset, c.recent_seg_val, c.recent_seg_idx] { put_u16(&mut p, v); }
+    for v in [c.last_event_code, c.last_event_spc, c.last_event_scs] { put_u16(&mut p, v); }
+    put_chunk(&mut out, TAG_CPU, &p);
+
+    p.clear();
+    put_u32(&mut p, c.mem.len() as u32);
+    rle_encode(&c.mem, &mut p);
+    put_chunk(&mut out, TAG_MEM, &p);
+
+    // Written words as (start, length) ranges.
+    p.clear();
+    let mut ranges = Vec::new();
+    let mut a = 0;
+    while a < c.mem.len() {
+        if !c.is_written(a) { a += 1; continue; }
+        let start = a;
+        while a < c.mem.len() && c.is_written(a) { a += 1; }
+        ranges.push((start as u32, (a - start) as u32));
+    }
+    put_u32(&mut p, ranges.len() as u32);
+    for (start, len) in ranges { put_u32(&mut p, start); put_u32(&mut p, len); }
+    put_chunk(&mut out, TAG_INIT, &p);
+
+    for (start, state) in c.bus.save() {
+        p.clear();
+        put_u32(&mut p, start as u32);
+        p.extend_from_slice(&state);
+        put_chunk(&mut out, TAG_DEV, &p);
+    }
+
+    put_chunk(&mut out, TAG_END, &[]);
+    out
+}
+
+/// Decodes `data` completely, then replaces the machine state with it.
+pub(crate) fn load(c: &mut Cpu, data: &[u8]) -> Result<(), SnapshotError> {
+    let mut r = Reader { data, at: 0 };
+    if r.bytes(4).map_err(|_| SnapshotError::BadMagic)? != MAGIC { return Err(SnapshotError::BadMagic); }
+    let version = r.u16()?;
+    if version == 0 || version > VERSION { return Err(SnapshotError::UnsupportedVersion(version)); }
+    r.u16()?;
+
+    let mut cpu_chunk = None;
+    let mut mem = None;
+    let mut init = None;
+    let mut devices = Vec::new();
+    loop {
+        let tag = r.bytes(4)?;
+        let len = r.u32()? as usize;
+        let mut chunk = Reader { data: r.bytes(len)?, at: 0 };
+        match tag {
+            t if t == TAG_END => break,
+            t if t == TAG_CPU => cpu_chunk = Some(chunk),
+            t if t == TAG_MEM => {
+                let words = chunk.u32()? as usize;
+                if words != c.mem.len() { return Err(SnapshotError::Corrupt("memory size differs from this machine")); }
+                mem = Some(rle_decode(&mut chunk, words)?);
+            }
+            t if t == TAG_INIT => {
+                let n = chunk.u32()?;
+                let mut ranges = Vec::new();
+                for _ in 0..n { ranges.push((chunk.u32()? as usize, chunk.u32()? as usize)); }
+                init = Some(ranges);
+            }
+            t if t == TAG_DEV => {
+                let start = chunk.u32()? as usize;
+                devices.push((start, &chunk.data[chunk.at..]));
+            }
+            _ => {}
+        }
+    }
+    if !r.is_empty() { return Err(SnapshotError::Corrupt("data after END chunk")); }
+    let mut p = cpu_chunk.ok_or(SnapshotError::MissingChunk("CPU"))?;
+    let mem = mem.ok_or(SnapshotError::MissingChunk("MEM"))?;
+    let init = init.ok_or(SnapshotError::MissingChunk("INIT"))?;
+    if init.iter().any(|&(start, len)| start.checked_add(len).is_none_or(|e| e > mem.len())) {
+        return Err(SnapshotError::Corrupt("written range past end of memory"));
+    }
+
+    let mut reg = [0u16; 16];
+    for v in reg.iter_mut() { *v = p.u16()?; }
+    let mut words = [0u16; 13];
+    for v in words.iter_mut() { *v = p.u16()?; }
+    let flags = p.u16()?;
+    let last_alu_result = p.u32()? as i32;
+    let recent_addr = p.u32()? as usize;
+    let mut recent = [0u16; 4];
+    for v in recent.iter_mut() { *v = p.u16()?; }
+    let mut event = [0u16; 3];
+    for v in event.iter_mut() { *v = p.u16()?; }
+
+    // Everything decoded; from here on nothing can fail.
+    c.written = vec![0; mem.len().div_ceil(64)];
+    c.mem = mem;
+    for (start, len) in init {
+        for a in start..start + len { c.mark_written(a); }
+    }
+    c.reg = reg;
+    [c.psw, c.spsw, c.cs, c.ds, c.ss, c.es, c.scs, c.spc, c.sds, c.sss, c.ses, c.delayed_pc, c.delayed_cs] = words;
+    let flag = |i: u32| flags & (1 << i) != 0;
+    c.delay_active = flag(0);
+    c.delayed_to_shadow = flag(1);
+    c.branch_taken = flag(2);
+    c.last_op_alu = flag(3);
+    c.irq_pending = flag(4);
+    c.irq_line = flag(5);
+    c.nmi_pending = flag(6);
+    c.nmi_active = flag(7);
+    c.recent_is_store = flag(8);
+    c.last_alu_result = last_alu_result;
+    c.recent_addr = recent_addr;
+    [c.recent_base, c.recent_offset, c.recent_seg_val, c.recent_seg_idx] = recent;
+    [c.last_event_code, c.last_event_spc, c.last_event_scs] = event;
+    c.bus.load(&devices);
+    c.running = false;
+    c.stop_reason = None;
+    c.fetch_addr = 0;
+    c.fetch_cs = 0;
+    c.fetch_pc = 0;
+    c.fetch_instr = 0;
+    c.timing = c.timing.cleared();
+    c.debug.resume_addr = None;
+    c.debug.watch_hit = None;
+    c.history.clear();
+    Ok(())
+}
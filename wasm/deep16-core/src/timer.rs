@@ -0,0 +1,139 @@
+//! Programmable interval timer at 0xF0020 (spec section 10.2.3).
+//!
+//! | Address | Register |
+//! |---------|----------|
+//! | 0xF0020 | Control (below) |
+//! | 0xF0022 | Value: the down-counter, readable and writable |
+//! | 0xF0024 | Reload: loaded into the counter on expiry and by control bit 3 |
+//! | 0xF0026 | Prescaler: the counter steps once every `prescaler + 1` clocks |
+//!
+//! Control bits: 0 run, 1 interrupt enable, 2 periodic (reload and keep
+//! running on expiry; otherwise stop), 3 load the reload value now (reads as
+//! 0), 4 expired (set when the counter steps to 0; write 1 to clear it).
+//!
+//! The timer is clocked by executed steps, or by cycles with the timing model
+//! on, and holds the interrupt line high while it has expired with interrupts
+//! enabled. A counter stepping from 0 wraps to 0xFFFF, so a reload of 0 gives
+//! the longest period.
+
+use crate::bus::Device;
+
+pub const TIMER_BASE: usize = 0xF0020;
+/// Words of I/O space the timer decodes.
+pub const TIMER_LEN: usize = 0x10;
+
+const CONTROL: usize = 0;
+const VALUE: usize = 2;
+const RELOAD: usize = 4;
+const PRESCALER: usize = 6;
+
+pub const TIMER_RUN: u16 = 1 << 0;
+pub const TIMER_IRQ: u16 = 1 << 1;
+pub const TIMER_PERIODIC: u16 = 1 << 2;
+pub const TIMER_LOAD: u16 = 1 << 3;
+pub const TIMER_EXPIRED: u16 = 1 << 4;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timer {
+    control: u16,
+    value: u16,
+    reload: u16,
+    prescaler: u16,
+    /// Clocks counted towards the next step of the counter.
+    prescale_count: u16,
+}
+
+impl Timer {
+    pub fn control(&self) -> u16 {
+        self.control
+    }
+
+    pub fn value(&self) -> u16 {
+        self.value
+    }
+
+    pub fn reload(&self) -> u16 {
+        self.reload
+    }
+
+    pub fn prescaler(&self) -> u16 {
+        self.prescaler
+    }
+
+    fn count_down(&mut self) {
+        self.value = self.value.wrapping_sub(1);
+        if self.value != 0 { return; }
+        self.control |= TIMER_EXPIRED;
+        if self.control & TIMER_PERIODIC != 0 { self.value = self.reload; } else { self.control &= !TIMER_RUN; }
+    }
+}
+
+impl Device for Timer {
+    fn read(&mut self, offset: usize) -> u16 {
+        self.peek(offset)
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        match offset {
+            CONTROL => self.control,
+            VALUE => self.value,
+            RELOAD => self.reload,
+            PRESCALER => self.prescaler,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16) {
+        match offset {
+            CONTROL => {
+                let expired = self.control & TIMER_EXPIRED & !value;
+                self.control = (value & (TIMER_RUN | TIMER_IRQ | TIMER_PERIODIC)) | expired;
+                if value & TIMER_LOAD != 0 {
+                    self.value = self.reload;
+                    self.prescale_count = 0;
+                }
+            }
+            VALUE => self.value = value,
+            RELOAD => self.reload = value,
+            PRESCALER => {
+                self.prescaler = value;
+                self.prescale_count = 0;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        let period = self.prescaler as u64 + 1;
+        let mut clocks = self.prescale_count as u64 + cycles;
+        while clocks >= period && self.control & TIMER_RUN != 0 {
+            clocks -= period;
+            self.count_down();
+        }
+        self.prescale_count = if self.control & TIMER_RUN != 0 { clocks as u16 } else { 0 };
+    }
+
+    fn irq(&self) -> bool {
+        self.control & (TIMER_IRQ | TIMER_EXPIRED) == TIMER_IRQ | TIMER_EXPIRED
+    }
+
+    fn reset(&mut self) {
+        *self = Timer::default();
+    }
+
+    fn save(&self) -> Vec<u8> {
+        [self.control, self.value, self.reload, self.prescaler, self.prescale_count]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect()
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        let mut words = data.chunks_exact(2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+        *self = Timer::default();
+        for field in [&mut self.control, &mut self.value, &mut self.reload, &mut self.prescaler, &mut self.prescale_count] {
+            let Some(v) = words.next() else { break; };
+            *field = v;
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Execution trace: a ring buffer of executed instructions with the
+//! register, flag and memory changes each one made.
+//!
+//! Tracing is off until `Cpu::set_trace` gives the buffer a capacity; once
+//! full, the oldest entries are dropped. Polling `Cpu::take_trace` empties
+//! it, so a front end can stream a long run in pieces. A `TraceFilter`
+//! limits recording to an address range and a set of instruction classes;
+//! `seq` keeps counting filtered-out instructions, so gaps stay visible.
+//!
+//! `trace_to_binary` packs entries as follows, little endian:
+//!
+//! ```text
+//! "D16T" u16 version=1
+//! per entry:
+//!   varint  seq delta from the previous entry (the first from 0)
+//!   u8      bit0 memory access, bit1 it is a store, bit2 PSW changed
+//!   u8      number of register deltas
+//!   u16 cs, u16 pc, u32 physical address, u16 instruction
+//!   [u16 psw before, u16 psw after]           if bit2
+//!   per delta: u8 register, u16 old, u16 new  (0-15 R0-R15, 16-19 CS DS SS ES)
+//!   [u32 address, u16 value]                  if bit0
+//! ```
+//!
+//! Varints are LEB128: seven bits per byte, low bits first, high bit set on
+//! all but the last byte.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use crate::cpu::Cpu;
+use crate::disasm::{disassemble_at, InstrClass};
+
+/// A register of the active context changed by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegDelta {
+    /// 0-15 for R0-R15, 16-19 for CS, DS, SS, ES.
+    pub reg: u8,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// The data memory access made by an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceMem {
+    /// Physical word address.
+    pub addr: usize,
+    /// Value loaded or stored.
+    pub value: u16,
+    pub is_store: bool,
+}
+
+/// One executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions executed since tracing was turned on, counting
+    /// from 0.
+    pub seq: u64,
+    pub cs: u16,
+    pub pc: u16,
+    /// Physical address of the instruction.
+    pub addr: usize,
+    pub instr: u16,
+    pub psw_before: u16,
+    pub psw_after: u16,
+    /// Registers and segment registers that changed, not counting the PC
+    /// moving on; a branch shows up as the CS:PC of the next entry.
+    pub regs: Vec<RegDelta>,
+    pub mem: Option<TraceMem>,
+}
+
+/// Which instructions get recorded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Inclusive range of instruction physical addresses.
+    pub start: usize,
+    pub end: usize,
+    /// `InstrClass::bit` mask.
+    pub classes: u32,
+}
+
+impl TraceFilter {
+    pub const ALL_CLASSES: u32 = u32::MAX;
+
+    fn accepts(&self, addr: usize, instr: u16) -> bool {
+        (self.start..=self.end).contains(&addr) && self.classes & InstrClass::of(instr).bit() != 0
+    }
+}
+
+impl Default for TraceFilter {
+    fn default() -> TraceFilter {
+        TraceFilter { start: 0, end: usize::MAX, classes: TraceFilter::ALL_CLASSES }
+    }
+}
+
+/// Machine state before the step being traced.
+#[derive(Clone, Copy, Debug)]
+struct Before {
+    regs: [u16; 16],
+    segs: [u16; 4],
+    psw: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Trace {
+    pub(crate) capacity: usize,
+    pub(crate) filter: TraceFilter,
+    pub(crate) entries: VecDeque<TraceEntry>,
+    seq: u64,
+    before: Option<Before>,
+    /// Instruction the current step executes, once it is issued.
+    issued: Option<u16>,
+    mem: Option<TraceMem>,
+}
+
+impl Trace {
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.seq = 0;
+    }
+
+    pub(crate) fn set_capacity(&mut self, capacity: usize) {
+        if self.capacity == 0 { self.seq = 0; }
+        self.capacity = capacity;
+        while self.entries.len() > capacity { self.entries.pop_front(); }
+    }
+}
+
+pub(crate) fn begin(c: &mut Cpu) {
+    if c.trace.capacity == 0 { return; }
+    c.trace.before = Some(Before { regs: c.registers(), segs: c.segments(), psw: c.psw });
+    c.trace.issued = None;
+    c.trace.mem = None;
+}
+
+/// Marks `instr` as executing in the current step.
+pub(crate) fn issue(c: &mut Cpu, instr: u16) {
+    if c.trace.before.is_some() { c.trace.issued = Some(instr); }
+}
+
+pub(crate) fn note_access(c: &mut Cpu, addr: usize, value: u16, is_store: bool) {
+    if c.trace.before.is_some() { c.trace.mem = Some(TraceMem { addr, value, is_store }); }
+}
+
+/// Records the step that just ran, if it executed an instruction the filter
+/// accepts.
+pub(crate) fn finish(c: &mut Cpu) {
+    let Some(before) = c.trace.before.take() else { return; };
+    let Some(instr) = c.trace.issued.take() else { return; };
+    let seq = c.trace.seq;
+    c.trace.seq += 1;
+    if !c.trace.filter.accepts(c.fetch_addr, instr) { return; }
+    let (regs, segs) = (c.registers(), c.segments());
+    let mut deltas = Vec::new();
+    let old = before.regs[..15].iter().chain(&before.segs);
+    let new = regs[..15].iter().chain(&segs);
+    for (i, (&old, &new)) in old.zip(new).enumerate() {
+        let reg = if i < 15 { i as u8 } else { i as u8 + 1 };
+        if old != new { deltas.push(RegDelta { reg, old, new }); }
+    }
+    let entry = TraceEntry {
+        seq,
+        cs: c.fetch_cs,
+        pc: c.fetch_pc,
+        addr: c.fetch_addr,
+        instr,
+        psw_before: before.psw,
+        psw_after: c.psw,
+        regs: deltas,
+        mem: c.trace.mem.take(),
+    };
+    let t = &mut c.trace;
+    if t.entries.len() == t.capacity { t.entries.pop_front(); }
+    t.entries.push_back(entry);
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// Packs entries in the binary trace format described above.
+pub fn trace_to_binary(entries: &[TraceEntry]) -> Vec<u8> {
+    let mut out = b"D16T".to_vec();
+    out.extend_from_slice(&1u16.to_le_bytes());
+    let mut last = 0;
+    for e in entries {
+        put_varint(&mut out, e.seq.wrapping_sub(last));
+        last = e.seq;
+        let psw_changed = e.psw_before != e.psw_after;
+        let flags = e.mem.is_some() as u8 | (e.mem.is_some_and(|m| m.is_store) as u8) << 1 | (psw_changed as u8) << 2;
+        out.push(flags);
+        out.push(e.regs.len() as u8);
+        for v in [e.cs, e.pc] { out.extend_from_slice(&v.to_le_bytes()); }
+        out.extend_from_slice(&(e.addr as u32).to_le_bytes());
+        out.extend_from_slice(&e.instr.to_le_bytes());
+        if psw_changed {
+            out.extend_from_slice(&e.psw_before.to_le_bytes());
+            out.extend_from_slice(&e.psw_after.to_le_bytes());
+        }
+        for d in &e.regs {
+            out.push(d.reg);
+            out.extend_from_slice(&d.old.to_le_bytes());
+            out.extend_from_slice(&d.new.to_le_bytes());
+        }
+        if let Some(m) = e.mem {
+            out.extend_from_slice(&(m.addr as u32).to_le_bytes());
+            out.extend_from_slice(&m.value.to_le_bytes());
+        }
+    }
+    out
+}
+
+const DELTA_NAMES: [&str; 20] = [
+    "R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "R8", "R9", "R10", "R11", "R12", "R13", "R14", "PC", "CS", "DS",
+    "SS", "ES",
+];
+const FLAG_NAMES: [(u16, char); 6] = [(0, 'N'), (1, 'Z'), (2, 'V'), (3, 'C'), (4, 'I'), (5, 'S')];
+
+/// One line per entry, e.g.
+///
+/// ```text
+///       12 0000:0102 00102 C301  SUB R0, #0x1           R0 0001->0000  +Z
+///       13 0000:0103 00103 A000  ST R0, [R0+0x0]        [00000]<-0000
+/// ```
+pub fn trace_to_text(entries: &[TraceEntry]) -> String {
+    let mut out = String::new();
+    for e in entries {
+        let text = disassemble_at(e.instr, e.pc);
+        let _ = write!(out, "{:>8} {:04X}:{:04X} {:05X} {:04X}  {text:<22}", e.seq, e.cs, e.pc, e.addr, e.instr);
+        for d in &e.regs {
+            let _ = write!(out, " {} {:04X}->{:04X}", DELTA_NAMES[d.reg as usize], d.old, d.new);
+        }
+        if let Some(m) = e.mem {
+            let arrow = if m.is_store { "<-" } else { "->" };
+            let _ = write!(out, " [{:05X}]{arrow}{:04X}", m.addr, m.value);
+        }
+        let changed = e.psw_before ^ e.psw_after;
+        if changed != 0 {
+            out.push(' ');
+            for (bit, name) in FLAG_NAMES {
+                if changed & (1 << bit) == 0 { continue; }
+                out.push(' ');
+                out.push(if e.psw_after & (1 << bit) != 0 { '+' } else { '-' });
+                out.push(name);
+            }
+            if changed & 0xFFC0 != 0 {
+                let _ = write!(out, " PSW {:04X}->{:04X}", e.psw_before, e.psw_after);
+            }
+        }
+        let trimmed = out.trim_end().len();
+        out.truncate(trimmed);
+        out.push('\n');
+    }
+    out
+}
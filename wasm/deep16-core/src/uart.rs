@@ -0,0 +1,132 @@
+//! Serial port at 0xF0040 (spec section 10.2.5).
+//!
+//! | Address | Register |
+//! |---------|----------|
+//! | 0xF0040 | Read: status in bits 0-1, interrupt enables in bits 8-9. Write: interrupt enables in bits 0-1 |
+//! | 0xF0042 | Data: reading takes the next received byte (0 if none), writing sends the low byte |
+//! | 0xF0044 | Baud rate divisor, stored for software but without effect on timing |
+//!
+//! Status bit 0 is RX ready, bit 1 TX ready. The host end of the line never
+//! stalls, so TX is always ready. Enable bit 0 requests an interrupt while a
+//! received byte waits, bit 1 while TX is ready; the interrupt line stays
+//! high until the condition goes away or the handler clears the enable.
+//!
+//! The host feeds received bytes with `Uart::write_input` and collects sent
+//! bytes with `Uart::read_output`, or has each one handed to a callback.
+
+use std::collections::VecDeque;
+
+use crate::bus::Device;
+
+pub const UART_BASE: usize = 0xF0040;
+/// Words of I/O space the serial port decodes.
+pub const UART_LEN: usize = 0x10;
+
+const STATUS: usize = 0;
+const DATA: usize = 2;
+const BAUD: usize = 4;
+
+pub const UART_RX_READY: u16 = 1 << 0;
+pub const UART_TX_READY: u16 = 1 << 1;
+const ENABLES: u16 = UART_RX_READY | UART_TX_READY;
+
+#[derive(Default)]
+pub struct Uart {
+    /// Interrupt enables, in status bit positions.
+    enables: u16,
+    baud: u16,
+    rx: VecDeque<u8>,
+    tx: Vec<u8>,
+    on_output: Option<Box<dyn FnMut(u8)>>,
+}
+
+impl Uart {
+    /// Queues bytes for the program to receive.
+    pub fn write_input(&mut self, bytes: &[u8]) {
+        self.rx.extend(bytes);
+    }
+
+    /// Removes and returns the bytes the program has sent. Bytes handed to
+    /// an output callback are not collected here.
+    pub fn read_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.tx)
+    }
+
+    /// Passes every byte the program sends to `f` as it is sent, instead of
+    /// collecting it for `read_output`; `None` goes back to collecting.
+    pub fn set_output_callback(&mut self, f: Option<Box<dyn FnMut(u8)>>) {
+        self.on_output = f;
+    }
+
+    /// Received bytes the program has not read yet.
+    pub fn pending_input(&self) -> usize {
+        self.rx.len()
+    }
+
+    fn status(&self) -> u16 {
+        let rx = if self.rx.is_empty() { 0 } else { UART_RX_READY };
+        rx | UART_TX_READY
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: usize) -> u16 {
+        match offset {
+            DATA => self.rx.pop_front().unwrap_or(0) as u16,
+            _ => self.peek(offset),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        match offset {
+            STATUS => self.status() | (self.enables << 8),
+            DATA => self.rx.front().copied().unwrap_or(0) as u16,
+            BAUD => self.baud,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16) {
+        match offset {
+            STATUS => self.enables = value & ENABLES,
+            DATA => {
+                let byte = value as u8;
+                match &mut self.on_output {
+                    Some(f) => f(byte),
+                    None => self.tx.push(byte),
+                }
+            }
+            BAUD => self.baud = value,
+            _ => {}
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.enables & self.status() != 0
+    }
+
+    /// Clears the registers and both byte streams; an output callback stays.
+    fn reset(&mut self) {
+        self.enables = 0;
+        self.baud = 0;
+        self.rx.clear();
+        self.tx.clear();
+    }
+
+    /// Enables and baud divisor as `u16`, then the bytes not yet received.
+    /// Sent bytes belong to the host and are not saved.
+    fn save(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(&self.enables.to_le_bytes());
+        out.extend_from_slice(&self.baud.to_le_bytes());
+        out.extend(&self.rx);
+        out
+    }
+
+    fn load(&mut self, data: &[u8]) {
+        let word = |i: usize| data.get(i..i + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]));
+        self.enables = word(0) & ENABLES;
+        self.baud = word(2);
+        self.rx = data.get(4..).unwrap_or_default().iter().copied().collect();
+    }
+}
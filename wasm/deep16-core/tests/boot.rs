@@ -0,0 +1,85 @@
+//! Power-on state, the boot ROM, program loading, reset and independent
+//! machines.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, StopReason, ILLEGAL_VECTOR, NMI_VECTOR};
+
+#[test]
+fn power_on_state_points_at_the_boot_rom() {
+    let cpu = Cpu::new(1 << 20);
+    assert_eq!(cpu.segments(), [0xFFFF, 0x1000, 0x8000, 0x2000]);
+    assert_eq!(cpu.registers()[13], 0x7FFF, "SP");
+    assert_eq!(cpu.registers()[15], 0);
+    assert_eq!(cpu.psw(), 0);
+    assert_eq!(cpu.stop_reason(), None);
+    assert!(!cpu.is_running());
+    assert_eq!(cpu.read_word(0xFFFF0), 0x0000, "ROM starts with LDI 0");
+    assert_eq!(cpu.read_word(0xFFFFD), 0xFE40, "JML R0");
+    assert_eq!(cpu.read_word(0x00100), 0xFFFF);
+}
+
+#[test]
+fn boot_rom_clears_segments_and_jumps_to_the_program() {
+    let mut cpu = boot(&[ldi(42), HLT]);
+    assert_eq!(cpu.segments(), [0, 0, 0, 0x2000]);
+    assert_eq!(cpu.memory()[..3], [0x0100; 3], "ROM stores R1 (1, byte-swapped) into words 0-2");
+    assert_eq!(cpu.memory()[NMI_VECTOR as usize], 0x0005, "default NMI handler");
+    assert_eq!(cpu.memory()[ILLEGAL_VECTOR as usize], 0x0005, "default trap handler");
+    assert_eq!(cpu.memory()[5], HLT);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.registers()[0], 42);
+}
+
+#[test]
+fn load_program_restarts_at_the_rom() {
+    let mut cpu = boot(&[ldi(1), ldi(2), HLT]);
+    cpu.step();
+    assert!(cpu.history_len() > 0);
+    assert!(cpu.load_program(0x0200, &[ldi(7)]));
+    assert_eq!(cpu.registers()[15], 0);
+    assert_eq!(cpu.segments()[0], 0xFFFF);
+    assert_eq!(cpu.history_len(), 0, "undo history is forgotten");
+    assert_eq!(cpu.memory()[0x0200], ldi(7));
+    run_boot_rom(&mut cpu);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.registers()[0], 2);
+}
+
+#[test]
+fn load_program_rejects_data_that_does_not_fit() {
+    let mut cpu = Cpu::new(0x100);
+    assert!(!cpu.load_program(0xFF, &[1, 2]));
+    assert!(!cpu.load_program(usize::MAX, &[1]));
+    assert_eq!(cpu.memory()[0xFF], 0xFFFF, "nothing loaded");
+    assert!(cpu.load_program(0xFE, &[1, 2]));
+    assert_eq!(cpu.memory()[0xFE..], [1, 2]);
+}
+
+#[test]
+fn reset_returns_to_power_on() {
+    let mut cpu = boot(&[ldi(5), st(0, 0, 0x10), HLT]);
+    assert!(!cpu.run(10));
+    cpu.reset();
+    let fresh = Cpu::new(1 << 20);
+    assert_eq!(cpu.registers(), fresh.registers());
+    assert_eq!(cpu.segments(), fresh.segments());
+    assert_eq!(cpu.psw(), fresh.psw());
+    assert_eq!(cpu.stop_reason(), None);
+    assert_eq!(cpu.memory(), fresh.memory(), "program and stores are gone");
+    run_boot_rom(&mut cpu);
+    assert!(!cpu.step());
+    assert_eq!(cpu.stop_reason(), Some(StopReason::FetchFromUninitialized { addr: 0x0100 }));
+}
+
+#[test]
+fn machines_are_independent() {
+    let mut a = boot(&[ldi(1), HLT]);
+    let mut b = boot(&[ldi(2), HLT]);
+    assert!(!a.run(10));
+    assert_eq!(b.registers()[0], 0, "b has not run yet");
+    assert!(!b.run(10));
+    assert_eq!((a.registers()[0], b.registers()[0]), (1, 2));
+}
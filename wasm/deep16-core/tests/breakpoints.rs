@@ -0,0 +1,155 @@
+//! Debugger stops in `run`: execution breakpoints (before the instruction,
+//! resuming, in a branch delay slot, conditional and ignore counts) and data
+//! watchpoints.
+
+mod common;
+
+use common::*;
+use deep16_core::{BreakAt, ExprError, StopReason, WatchKind};
+
+#[test]
+fn run_stops_before_breakpoint_and_resumes() {
+    let mut cpu = boot(&[ldi(1), ldi(2), ldi(3), HLT]);
+    let id = cpu.add_breakpoint(BreakAt::Phys(0x0102));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0102 }));
+    assert_eq!(cpu.registers()[0], 2, "breakpointed instruction must not run");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.registers()[0], 3);
+}
+
+#[test]
+fn cs_pc_breakpoint_and_removal() {
+    let mut cpu = boot(&[ldi(1), ldi(2), ldi(3), HLT]);
+    let id = cpu.add_breakpoint(BreakAt::CsPc(0x0000, 0x0101));
+    cpu.add_breakpoint(BreakAt::CsPc(0x0010, 0x0002));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0101 }));
+    assert!(cpu.remove_breakpoint(id));
+    assert!(!cpu.remove_breakpoint(id));
+    // 0010:0002 is the same physical word as 0000:0102, but not the same CS:PC
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+}
+
+#[test]
+fn single_step_ignores_breakpoints() {
+    let mut cpu = boot(&[ldi(1), ldi(2), HLT]);
+    cpu.add_breakpoint(BreakAt::Phys(0x0100));
+    assert!(cpu.step());
+    assert_eq!(cpu.registers()[0], 1);
+}
+
+#[test]
+fn breakpoint_in_delay_slot_keeps_pending_branch() {
+    // LDI 1 clears Z, so JNZ is taken to 0x0104; the delay slot copies R0 to R2.
+    let mut cpu = boot(&[ldi(1), jnz(2), mov(2, 0), HLT, ldi(5), HLT]);
+    let id = cpu.add_breakpoint(BreakAt::Phys(0x0102));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0102 }));
+    assert_eq!(cpu.registers()[2], 0);
+    assert_eq!(cpu.registers()[15], 0x0102);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.registers()[2], 1, "delay slot runs after resuming");
+    assert_eq!(cpu.registers()[0], 5, "branch is still taken");
+}
+
+/// Counts R0 down from 4; the delay slot counts iterations in R1.
+const COUNTDOWN: [u16; 5] = [0x0004, 0xC301, 0xE3FE, 0xC111, HLT];
+
+#[test]
+fn conditional_breakpoint_stops_when_true() {
+    assert_eq!(COUNTDOWN[1..4], [sub_imm(0, 1), jnz(-2), add_imm(1, 1)]);
+    let mut cpu = boot(&COUNTDOWN);
+    cpu.set_register(1, 0);
+    let id = cpu.add_conditional_breakpoint(BreakAt::Phys(0x0102), "r0 == 1 && !Z", 0).unwrap();
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0102 }));
+    assert_eq!(cpu.registers()[1], 2);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.breakpoint_hits(id), Some(1));
+}
+
+#[test]
+fn ignore_count_skips_early_hits() {
+    let mut cpu = boot(&COUNTDOWN);
+    let id = cpu.add_conditional_breakpoint(BreakAt::Phys(0x0101), "", 2).unwrap();
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0101 }));
+    assert_eq!(cpu.registers()[0], 2, "third pass through the loop");
+    assert_eq!(cpu.breakpoint_hits(id), Some(3));
+    assert!(cpu.set_breakpoint_ignore_count(id, 5));
+    assert_eq!(cpu.breakpoint_hits(id), Some(0));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+}
+
+#[test]
+fn condition_parse_errors_are_reported() {
+    let mut cpu = boot(&COUNTDOWN);
+    cpu.set_register(1, 0);
+    let err = |pos: usize, msg: &str| ExprError { pos, msg: msg.into() };
+    let at = BreakAt::Phys(0x0102);
+    assert_eq!(cpu.add_conditional_breakpoint(at, "R16 == 1", 0), Err(err(0, "unknown name 'R16'")));
+    assert_eq!(cpu.add_conditional_breakpoint(at, "R1 = 2", 0), Err(err(3, "use '==' to compare")));
+    assert_eq!(cpu.add_conditional_breakpoint(at, "(R1 + 2", 0), Err(err(7, "expected ')'")));
+    let id = cpu.add_conditional_breakpoint(at, "[0x100] == 0b100 && pc == 0x0102 && R0 == 0", 0).unwrap();
+    assert_eq!(cpu.set_breakpoint_condition(id, "SP >"), Err(err(4, "unexpected end of condition")));
+    assert!(cpu.set_breakpoint_condition(id + 1, "Z").is_err());
+    assert!(cpu.set_breakpoint_condition(id, "[0x80000 + SP] != 0 || !C").is_ok());
+    assert!(cpu.set_breakpoint_condition(id, "[0x100] == 0b100 && pc == 0x0102 && R0 == 0").is_ok());
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Breakpoint { id, addr: 0x0102 }));
+    assert_eq!(cpu.registers()[1], 3);
+}
+
+#[test]
+fn condition_nesting_is_limited() {
+    let mut cpu = boot(&COUNTDOWN);
+    let at = BreakAt::Phys(0x0102);
+    let deep = |open: &str, close: &str, n| format!("{}R0{} == 0", open.repeat(n), close.repeat(n));
+    for (open, close) in [("(", ")"), ("[", "]"), ("-", ""), ("!~", "")] {
+        assert!(cpu.add_conditional_breakpoint(at, &deep(open, close, 63 / open.len()), 0).is_ok(), "{open} at the limit");
+        let err = cpu.add_conditional_breakpoint(at, &deep(open, close, 65), 0).unwrap_err();
+        assert_eq!(err.msg, "condition nested more than 64 levels deep", "{open}");
+        assert_eq!(err.pos, 64, "{open}: reported at the first operator past the limit");
+    }
+    // Chains count too: each operator adds a level to the tree
+    assert!(cpu.add_conditional_breakpoint(at, &vec!["R1"; 64].join("+"), 0).is_ok());
+    assert!(cpu.add_conditional_breakpoint(at, &vec!["R1"; 66].join("+"), 0).is_err());
+    let huge = format!("{}Z", "!".repeat(1 << 20));
+    assert!(cpu.add_conditional_breakpoint(at, &huge, 0).is_err(), "no stack overflow");
+    assert!(cpu.add_conditional_breakpoint(at, &vec!["Z"; 1 << 16].join(" || "), 0).is_err());
+}
+
+#[test]
+fn write_watchpoint_reports_access() {
+    // DS is 0 after boot, so ST R0, [R0+0] writes 0x0050 to 0x00050.
+    let mut cpu = boot(&[ldi(0x50), st(0, 0, 0), ldi(1), HLT]);
+    let id = cpu.add_watchpoint(0x0040, 0x005F, WatchKind::Write);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Watchpoint { id, addr: 0x0050 }));
+    let hit = cpu.watch_hit().unwrap();
+    assert_eq!((hit.instr, hit.instr_addr, hit.cs, hit.pc), (st(0, 0, 0), 0x0101, 0, 0x0101));
+    assert!(hit.is_store);
+    assert_eq!((hit.old_value, hit.new_value), (0xFFFF, 0x0050));
+    assert_eq!((hit.seg_idx, hit.seg_val), (1, 0));
+    assert_eq!(cpu.registers()[15], 0x0102, "stops after the accessing instruction");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+}
+
+#[test]
+fn watch_kind_filters_reads_and_writes() {
+    let mut cpu = boot(&[ldi(0x50), st(0, 0, 0), ld(1, 0, 0), HLT]);
+    let id = cpu.add_watchpoint(0x0050, 0x0050, WatchKind::Read);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Watchpoint { id, addr: 0x0050 }));
+    let hit = cpu.watch_hit().unwrap();
+    assert!(!hit.is_store);
+    assert_eq!((hit.old_value, hit.new_value), (0x0050, 0x0050));
+    assert!(cpu.remove_watchpoint(id));
+}
@@ -0,0 +1,153 @@
+//! Memory-mapped devices: read side effects (and fetches without them),
+//! writes that bypass RAM, clocking, the interrupt line and the device map
+//! itself.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, Device, HW_INT_VECTOR};
+
+const BASE: usize = 0xF0050;
+
+/// Offset 0 counts its reads, offset 1 keeps what was written, writing
+/// offset 2 acknowledges the interrupt it raises once `irq_after` cycles
+/// have gone by.
+#[derive(Default)]
+struct Probe {
+    reads: u16,
+    writes: Vec<u16>,
+    cycles: u64,
+    irq_after: u64,
+    acks: u32,
+    resets: u32,
+}
+
+impl Device for Probe {
+    fn read(&mut self, offset: usize) -> u16 {
+        let v = self.peek(offset);
+        if offset == 0 { self.reads += 1; }
+        v
+    }
+
+    fn peek(&self, offset: usize) -> u16 {
+        match offset {
+            0 => self.reads,
+            1 => self.writes.last().copied().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u16) {
+        match offset {
+            1 => self.writes.push(value),
+            2 => { self.acks += 1; self.irq_after = 0; }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_after != 0 && self.cycles >= self.irq_after
+    }
+
+    fn reset(&mut self) {
+        *self = Probe { resets: self.resets + 1, ..Probe::default() };
+    }
+}
+
+/// Boots into `program` at 0000:0100 with ES pointing at the I/O segment
+/// and a `Probe` mapped at 0xF0050.
+fn boot_probe(program: &[u16], probe: Probe) -> Cpu {
+    let mut cpu = Cpu::new(1 << 20);
+    assert!(cpu.map_device(BASE, 4, probe));
+    cpu.load_program(0x0100, program);
+    run_boot_rom(&mut cpu);
+    cpu.set_segments(0, 0, 0, 0xF000);
+    cpu
+}
+
+#[test]
+fn loads_and_stores_reach_the_device() {
+    let mut cpu = boot_probe(&[lds_es(2, 1), lds_es(2, 1), sts_es(2, 3), HLT], Probe::default());
+    cpu.set_register(1, 0x50);
+    cpu.set_register(3, 0x51);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.registers()[2], 1, "second read sees the first one's side effect");
+    let probe = cpu.device::<Probe>().unwrap();
+    assert_eq!((probe.reads, probe.writes.as_slice()), (2, &[1][..]));
+    assert_eq!(cpu.memory()[BASE + 1], 0xFFFF, "RAM behind the device is untouched");
+    assert_eq!(cpu.read_word(BASE), 2);
+    assert_eq!(cpu.device::<Probe>().unwrap().reads, 2, "peeking has no side effects");
+    cpu.write_word(BASE + 1, 0x55);
+    assert_eq!(cpu.device::<Probe>().unwrap().writes, [1, 0x55]);
+}
+
+#[test]
+fn devices_are_clocked_and_interrupt() {
+    let mut cpu = boot_probe(&[SETI, NOP, NOP, NOP, NOP, HLT], Probe::default());
+    cpu.write_word(0x0200, sts_es(0, 3));
+    cpu.write_word(0x0201, RETI);
+    cpu.write_word(HW_INT_VECTOR as usize, 0x0200);
+    cpu.set_register(3, 0x52);
+    let probe = cpu.device_mut::<Probe>().unwrap();
+    let before = probe.cycles;
+    probe.irq_after = before + 3;
+    assert!(!cpu.run(100));
+    let probe = cpu.device::<Probe>().unwrap();
+    assert_eq!(probe.acks, 1, "handler ran once and dropped the line");
+    // SETI, two NOPs, the interrupt entry, STS, RETI, the other two NOPs
+    assert_eq!(probe.cycles - before, 8);
+}
+
+#[test]
+fn ticks_follow_cycles_with_timing_on() {
+    let mut cpu = boot_probe(&[NOP, NOP, NOP, HLT], Probe::default());
+    cpu.set_timing(true);
+    let before = cpu.device::<Probe>().unwrap().cycles;
+    assert!(!cpu.run(100));
+    let ticked = cpu.device::<Probe>().unwrap().cycles - before;
+    assert_eq!(ticked, cpu.cycle_counters().cycles);
+}
+
+#[test]
+fn fetching_from_a_device_peeks_it() {
+    // JML R2 to F005:0000; every probe register peeks as 0, i.e. LDI 0.
+    let mut cpu = boot_probe(&[jml(2), NOP], Probe::default());
+    cpu.set_register(2, 0xF005);
+    cpu.set_register(3, 0);
+    cpu.set_register(0, 7);
+    for _ in 0..4 { assert!(cpu.step()); }
+    assert_eq!((cpu.registers()[0], cpu.registers()[15]), (0, 2));
+    assert_eq!(cpu.device::<Probe>().unwrap().reads, 0, "no read side effects");
+}
+
+#[test]
+fn read_words_sees_devices_and_memory_sees_ram() {
+    let mut cpu = boot_probe(&[HLT], Probe::default());
+    cpu.write_word(BASE + 1, 0x1234);
+    cpu.device_mut::<Probe>().unwrap().reads = 5;
+    assert_eq!(cpu.read_words(BASE, 2), [5, 0x1234]);
+    assert_eq!(cpu.memory()[BASE..BASE + 2], [0xFFFF, 0xFFFF]);
+    assert_eq!(cpu.device::<Probe>().unwrap().reads, 5);
+    assert_eq!(cpu.read_words(cpu.memory().len() - 1, 2), [0xFFFF, 0xFFFF], "past the end");
+}
+
+#[test]
+fn device_map() {
+    let mut cpu = Cpu::new(16);
+    assert!(cpu.map_device(BASE, 4, Probe::default()));
+    assert!(!cpu.map_device(BASE + 3, 2, Probe::default()), "overlap");
+    assert!(!cpu.map_device(0, 0, Probe::default()), "empty range");
+    assert!(cpu.map_device(BASE + 4, 2, Probe::default()));
+    cpu.write_word(BASE + 1, 9);
+    assert_eq!(cpu.read_word(BASE + 1), 9, "mapped past the end of RAM");
+    cpu.reset();
+    assert_eq!(cpu.device::<Probe>().unwrap().resets, 1);
+    assert_eq!(cpu.read_word(BASE + 1), 0);
+    assert!(cpu.unmap_device(BASE).is_some());
+    assert!(cpu.unmap_device(BASE).is_none());
+    assert_eq!(cpu.read_word(BASE + 1), 0xFFFF);
+}
@@ -0,0 +1,109 @@
+//! Fixtures shared by the integration tests: instruction encoders and a
+//! machine booted through the ROM into a test program.
+
+#![allow(dead_code)]
+
+use deep16_core::{Cpu, SWI_VECTOR};
+
+pub const NOP: u16 = 0xFFF0;
+pub const FSH: u16 = 0xFFF1;
+pub const SWI: u16 = 0xFFF2;
+pub const RETI: u16 = 0xFFF3;
+pub const HLT: u16 = 0xFFFF;
+/// SET 4: sets PSW.I.
+pub const SETI: u16 = 0xFEC4;
+
+pub const PSW_N: u16 = 1 << 0;
+pub const PSW_Z: u16 = 1 << 1;
+pub const PSW_V: u16 = 1 << 2;
+pub const PSW_C: u16 = 1 << 3;
+pub const PSW_I: u16 = 1 << 4;
+pub const PSW_S: u16 = 1 << 5;
+
+/// Where `boot` loads the program and where the boot ROM jumps to.
+pub const ENTRY: u16 = 0x0100;
+
+pub fn ldi(imm: u16) -> u16 { imm & 0x7FFF }
+pub fn mov(rd: u16, rs: u16) -> u16 { 0xF800 | (rd << 6) | (rs << 2) }
+pub fn ld(rd: u16, rb: u16, off: u16) -> u16 { 0x8000 | (rd << 9) | (rb << 5) | off }
+pub fn st(rd: u16, rb: u16, off: u16) -> u16 { 0xA000 | (rd << 9) | (rb << 5) | off }
+pub fn add_imm(rd: u16, imm: u16) -> u16 { 0xC100 | (rd << 4) | imm }
+pub fn sub_imm(rd: u16, imm: u16) -> u16 { 0xC300 | (rd << 4) | imm }
+pub fn and_imm(rd: u16, imm: u16) -> u16 { 0xC700 | (rd << 4) | imm }
+/// MUL32 Rd, Rs: Rd:Rd+1 = Rd * Rs.
+pub fn mul32(rd: u16, rs: u16) -> u16 { 0xDD00 | (rd << 4) | rs }
+pub fn cmp(rd: u16, rs: u16) -> u16 { 0xC400 | (rd << 4) | rs }
+pub fn jz(off: i16) -> u16 { 0xE000 | (off as u16 & 0x1FF) }
+pub fn jnz(off: i16) -> u16 { 0xE200 | (off as u16 & 0x1FF) }
+pub fn lds_es(rd: u16, rs: u16) -> u16 { 0xF300 | (rd << 4) | rs }
+pub fn sts_es(rd: u16, rs: u16) -> u16 { 0xF700 | (rd << 4) | rs }
+pub fn neg(rx: u16) -> u16 { 0xFE20 | rx }
+pub fn jml(rx: u16) -> u16 { 0xFE40 | rx }
+pub fn srs(rx: u16) -> u16 { 0xFE80 | rx }
+pub fn erd(rx: u16) -> u16 { 0xFEB0 | rx }
+pub fn set(bit: u16) -> u16 { 0xFEC0 | bit }
+pub fn clr(bit: u16) -> u16 { 0xFED0 | bit }
+/// SET2: sets PSW bit `imm + 4`.
+pub fn set2(imm: u16) -> u16 { 0xFEE0 | imm }
+/// CLR2: clears PSW bit `imm + 4`.
+pub fn clr2(imm: u16) -> u16 { 0xFEF0 | imm }
+/// MVS Rd, Sx (segment 0=CS, 1=DS, 2=SS, 3=ES).
+pub fn mvs_get(rd: u16, seg: u16) -> u16 { 0xFF00 | (rd << 2) | seg }
+/// MVS Sx, Rd.
+pub fn mvs_set(seg: u16, rd: u16) -> u16 { 0xFF40 | (rd << 2) | seg }
+/// LPSW Rx: Rx = PSW.
+pub fn lpsw(rx: u16) -> u16 { 0xFFE0 | rx }
+/// SMV alt: alternate register `sel` (0=ACS .. 4=APC, 5=APSW) = R0.
+pub fn smv_write(sel: u16) -> u16 { 0xFFC0 | sel }
+/// SMV R0, alt.
+pub fn smv_read(sel: u16) -> u16 { 0xFFD0 | sel }
+
+/// Steps the boot ROM until it has jumped to 0000:0100. The ROM writes the
+/// interrupt vectors, so handlers must be installed afterwards.
+pub fn run_boot_rom(cpu: &mut Cpu) {
+    for _ in 0..32 {
+        if cpu.segments()[0] == 0 && cpu.registers()[15] == ENTRY { break; }
+        cpu.step();
+    }
+    assert_eq!(cpu.registers()[15], ENTRY, "boot ROM did not reach the program");
+}
+
+/// A fresh 1M-word machine booted into `program` at 0000:0100.
+pub fn boot(program: &[u16]) -> Cpu {
+    let mut cpu = Cpu::new(1 << 20);
+    cpu.load_program(ENTRY as usize, program);
+    run_boot_rom(&mut cpu);
+    cpu
+}
+
+/// `boot`, with ES pointing at the I/O segment.
+pub fn boot_io(program: &[u16]) -> Cpu {
+    let mut cpu = boot(program);
+    cpu.set_segments(0, 0, 0, 0xF000);
+    cpu
+}
+
+/// Writes `code` at 0000:`at` and points interrupt vector `vector` at it.
+pub fn install_handler(cpu: &mut Cpu, vector: u16, at: u16, code: &[u16]) {
+    for (i, &w) in code.iter().enumerate() { cpu.write_word(at as usize + i, w); }
+    cpu.write_word(vector as usize, at);
+}
+
+/// `boot`, with `handler` at 0000:0200 as the SWI handler.
+pub fn boot_with_handler(program: &[u16], handler: &[u16]) -> Cpu {
+    let mut cpu = boot(program);
+    install_handler(&mut cpu, SWI_VECTOR, 0x0200, handler);
+    cpu
+}
+
+/// Boots into a program at 0000:0100 that stores, then takes a branch with SWI
+/// in its delay slot; the handler at 0000:0200 stores too and returns. The
+/// boot ROM's steps are dropped from the undo history.
+pub fn boot_swi() -> Cpu {
+    let mut cpu = boot_with_handler(
+        &[ldi(0x50), st(0, 0, 0), ldi(1), jnz(2), SWI, HLT, ldi(9), HLT],
+        &[st(0, 0, 1), RETI],
+    );
+    cpu.clear_history();
+    cpu
+}
@@ -0,0 +1,163 @@
+//! Stop reasons for faults: where PC is left and that the faulting
+//! instruction changes nothing; and the three illegal-instruction policies.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, IllegalPolicy, StopReason, EVENT_TRAP, ILLEGAL_VECTOR};
+
+/// Everything a fault must leave alone, apart from PC.
+fn state(cpu: &Cpu) -> ([u16; 15], [u16; 4], u16, [u16; 6]) {
+    let r = cpu.registers();
+    (r[..15].try_into().unwrap(), cpu.segments(), cpu.psw(), cpu.shadow_state())
+}
+
+/// Steps through `before` instructions, then expects the next step to stop
+/// with `reason`, PC at `pc` and nothing else changed.
+fn expect_fault(mut cpu: Cpu, before: usize, reason: StopReason, pc: u16) -> Cpu {
+    for _ in 0..before { assert!(cpu.step()); }
+    let (saved, mem) = (state(&cpu), cpu.memory().to_vec());
+    assert!(!cpu.step());
+    assert_eq!(cpu.stop_reason(), Some(reason));
+    assert!(!cpu.is_running());
+    assert_eq!(cpu.registers()[15], pc);
+    assert_eq!(state(&cpu), saved);
+    assert!(cpu.memory() == mem, "memory untouched");
+    cpu
+}
+
+#[test]
+fn running_off_the_program_stops_at_the_unwritten_word() {
+    let cpu = boot(&[ldi(3), ldi(4)]);
+    let mut cpu = expect_fault(cpu, 2, StopReason::FetchFromUninitialized { addr: 0x0102 }, 0x0102);
+    assert_eq!(cpu.registers()[0], 4);
+    assert!(!cpu.step(), "stepping again faults again");
+    assert_eq!(cpu.registers()[15], 0x0102);
+}
+
+#[test]
+fn data_access_past_memory_stops_after_the_instruction() {
+    // DS = 0xFFFF puts DS:0x0100 at 0x1000F0, past the 1M words of RAM.
+    for instr in [ld(2, 3, 0), st(2, 3, 0)] {
+        let mut cpu = boot(&[mvs_set(1, 1), instr, HLT]);
+        cpu.set_register(1, 0xFFFF);
+        cpu.set_register(2, 0x1234);
+        cpu.set_register(3, 0x0100);
+        let cpu = expect_fault(cpu, 1, StopReason::PhysicalAddressOutOfRange { addr: 0x1000F0 }, 0x0102);
+        assert_eq!(cpu.registers()[2], 0x1234);
+    }
+}
+
+#[test]
+fn fetch_past_memory_stops_at_the_target() {
+    // JML R2 to FFFF:0100, then its delay slot; the fetch at the target faults.
+    let mut cpu = boot(&[jml(2), NOP, HLT]);
+    cpu.set_register(2, 0xFFFF);
+    cpu.set_register(3, 0x0100);
+    let cpu = expect_fault(cpu, 2, StopReason::PhysicalAddressOutOfRange { addr: 0x1000F0 }, 0x0100);
+    assert_eq!(cpu.segments()[0], 0xFFFF);
+}
+
+#[test]
+fn odd_register_pairs_stop_after_the_instruction() {
+    for instr in [jml(3), mul32(1, 4)] {
+        let mut cpu = boot(&[instr, NOP, HLT]);
+        for r in 1..6 { cpu.set_register(r, r as u16 * 0x11); }
+        let mut cpu = expect_fault(cpu, 0, StopReason::OddRegisterPairViolation { addr: 0x0100, instr }, 0x0101);
+        assert!(!cpu.run(10));
+        assert_eq!(cpu.stop_reason(), Some(StopReason::Halted), "no branch was left pending");
+        assert_eq!(cpu.registers()[15], 0x0102);
+        assert_eq!(cpu.registers()[1], 0x11);
+    }
+}
+
+#[test]
+fn hlt_in_a_delay_slot_keeps_the_branch_pending() {
+    // LDI 1 clears Z, so JNZ is taken to 0x0104 with HLT in its delay slot.
+    let mut cpu = boot(&[ldi(1), jnz(2), HLT, HLT, ldi(5), HLT]);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.registers()[15], 0x0102, "stopped on the slot");
+    assert!(!cpu.run(10), "resuming halts on it again");
+    assert_eq!(cpu.registers()[15], 0x0102);
+    cpu.write_word(0x0102, NOP);
+    assert!(!cpu.run(10));
+    assert_eq!((cpu.registers()[0], cpu.registers()[15]), (5, 0x0105), "the branch is taken after the slot");
+}
+
+#[test]
+fn fault_in_a_delay_slot_keeps_the_branch_pending() {
+    // DS = 0xFFFF, so the LD in the delay slot of JNZ (taken to 0x0105)
+    // reads past memory.
+    let slot = ld(2, 3, 0);
+    let mut cpu = boot(&[mvs_set(1, 1), ldi(1), jnz(2), slot, HLT, ldi(5), HLT]);
+    cpu.set_register(1, 0xFFFF);
+    cpu.set_register(2, 0x1234);
+    cpu.set_register(3, 0x0100);
+    let mut cpu = expect_fault(cpu, 3, StopReason::PhysicalAddressOutOfRange { addr: 0x1000F0 }, 0x0103);
+    assert_eq!(cpu.last_event_code(), slot);
+    cpu.set_register(3, 0);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    let r = cpu.registers();
+    assert_eq!((r[0], r[2], r[15]), (5, 0x0000, 0x0106), "the slot is retried, then the branch taken");
+}
+
+/// An unassigned encoding at 0000:0100, followed by LDI 7 and HLT, with a
+/// trap handler at 0000:0400 that counts in R8.
+fn boot_illegal(policy: IllegalPolicy) -> Cpu {
+    let mut cpu = boot(&[0xFF80, ldi(7), HLT]);
+    install_handler(&mut cpu, ILLEGAL_VECTOR, 0x0400, &[add_imm(8, 1), RETI]);
+    cpu.set_register(8, 0);
+    cpu.set_psw(PSW_C | PSW_I);
+    cpu.set_illegal_policy(policy);
+    cpu
+}
+
+#[test]
+fn ignored_illegal_instructions_are_nops() {
+    let mut cpu = boot_illegal(IllegalPolicy::Ignore);
+    assert!(cpu.step());
+    assert_eq!((cpu.registers()[15], cpu.psw(), cpu.stop_reason()), (0x0101, PSW_C | PSW_I, None));
+    assert_eq!(cpu.last_event_code(), 0xFF80);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.registers()[0], cpu.registers()[8]), (7, 0), "no trap taken");
+}
+
+#[test]
+fn illegal_instructions_can_halt() {
+    let cpu = boot_illegal(IllegalPolicy::Halt);
+    let cpu = expect_fault(cpu, 0, StopReason::IllegalInstruction { addr: 0x0100, instr: 0xFF80 }, 0x0101);
+    assert_eq!(cpu.psw(), PSW_C | PSW_I);
+    assert_eq!(cpu.registers()[8], 0, "no trap taken");
+}
+
+#[test]
+fn illegal_instructions_can_trap() {
+    let mut cpu = boot_illegal(IllegalPolicy::Trap);
+    assert!(cpu.step());
+    assert_eq!(cpu.stop_reason(), None);
+    assert_eq!(cpu.last_event_code(), EVENT_TRAP);
+    assert_eq!(cpu.psw(), PSW_C | PSW_S, "entered like SWI");
+    assert_eq!(cpu.registers()[15], 0x0400, "through ILLEGAL_VECTOR");
+    assert_eq!(cpu.shadow_state()[2], PSW_C | PSW_I);
+    cpu.step();
+    cpu.step();
+    assert_eq!((cpu.registers()[15], cpu.psw()), (0x0101, PSW_C | PSW_I), "RETI returns past the illegal word");
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.registers()[0], cpu.registers()[8]), (7, 1));
+}
+
+#[test]
+fn trap_without_a_handler_halts() {
+    // The boot ROM points ILLEGAL_VECTOR at a HLT word at 0000:0005.
+    let mut cpu = boot(&[0xFF80, ldi(7), HLT]);
+    cpu.set_illegal_policy(IllegalPolicy::Trap);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.last_event_code(), EVENT_TRAP);
+    assert_eq!((cpu.psw() & PSW_S, cpu.registers()[15]), (PSW_S, 0x0005));
+    assert_eq!(cpu.normal_state()[0], 0x0101, "past the illegal word");
+}
@@ -0,0 +1,302 @@
+//! SWI -> RETI regression flows (return address, PSW restoration, delay-slot
+//! flushing and nesting), hardware interrupt acceptance and NMIs.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, StopReason, EVENT_IRQ, EVENT_NMI, EVENT_RETI, EVENT_SWI, HW_INT_VECTOR, NMI_VECTOR, SWI_VECTOR};
+
+fn run_to_halt(cpu: &mut Cpu) {
+    assert!(!cpu.run(1000), "program did not halt");
+}
+
+#[test]
+fn reti_resumes_after_swi() {
+    let mut cpu = boot_with_handler(&[SWI, ldi(7), HLT], &[RETI]);
+    cpu.step();
+    assert_ne!(cpu.psw() & PSW_S, 0);
+    assert_eq!(cpu.registers()[15], 0x0200);
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0);
+    assert_eq!(cpu.registers()[15], 0x0101);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[0], 7);
+}
+
+#[test]
+fn reti_restores_flags_and_segment_selections() {
+    // CMP R1, R1 leaves Z=1, C=0.
+    let mut cpu = boot_with_handler(
+        &[srs(13), erd(10), ldi(0), mov(1, 0), cmp(1, 1), SWI, HLT],
+        &[ldi(1), add_imm(0, 15), srs(3), erd(4), RETI],
+    );
+    for _ in 0..5 { cpu.step(); }
+    let before = cpu.psw();
+    assert_ne!(before & PSW_Z, 0);
+    cpu.step();
+    for _ in 0..4 { cpu.step(); }
+    assert_ne!(cpu.psw(), before, "handler should have changed flags and SR/ER");
+    cpu.step();
+    assert_eq!(cpu.psw(), before);
+    assert_eq!(cpu.psw() & PSW_C, 0);
+}
+
+#[test]
+fn reti_reenables_interrupts() {
+    let mut cpu = boot_with_handler(&[SWI, HLT], &[RETI]);
+    cpu.set_psw(PSW_I);
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_I, 0, "handler runs with interrupts disabled");
+    cpu.step();
+    assert_eq!(cpu.psw(), PSW_I);
+}
+
+#[test]
+fn reti_keeps_shadow_context_for_inspection() {
+    let mut cpu = boot_with_handler(&[SWI, HLT], &[ldi(0x0123), 0xFF41 /* MVS DS, R0 */, RETI]);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.segments()[1], 0x0000);
+    let [spc, scs, spsw, sds, ..] = cpu.shadow_state();
+    assert_eq!((spc, scs, sds), (0x0203, 0x0000, 0x0123));
+    assert_eq!(spsw & PSW_S, 0);
+}
+
+#[test]
+fn reti_in_delay_slot_discards_the_branch() {
+    // JNZ taken (Z=0 after LDI 1) with RETI in its delay slot.
+    let mut cpu = boot_with_handler(&[SWI, ldi(5), HLT], &[ldi(1), jnz(-2), RETI, HLT]);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.psw() & PSW_S, 0);
+    assert_eq!(cpu.registers()[0], 5);
+    assert_eq!(cpu.registers()[15], 0x0102);
+}
+
+#[test]
+fn swi_in_delay_slot_returns_to_branch_target() {
+    // JNZ to 0x0105 skips LDI 9; SWI sits in its delay slot.
+    let mut cpu = boot_with_handler(&[ldi(1), jnz(3), SWI, ldi(9), HLT, ldi(5), HLT], &[RETI]);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[0], 5);
+}
+
+#[test]
+fn nested_swi_returns_to_normal_context() {
+    // The first handler retargets the vector to a second handler and issues
+    // SWI again; the second handler's RETI must land after the original SWI.
+    let mut cpu = boot_with_handler(
+        &[ldi(3), mov(2, 0), SWI, ldi(8), HLT],
+        &[ldi(0x0210), mov(1, 0), ldi(0), st(1, 0, SWI_VECTOR), SWI, HLT],
+    );
+    cpu.write_word(0x0210, ldi(0x0042));
+    cpu.write_word(0x0211, RETI);
+    let psw_before_swi = {
+        cpu.step();
+        cpu.step();
+        cpu.psw()
+    };
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.psw(), psw_before_swi);
+    assert_eq!(cpu.registers()[0], 8);
+    assert_eq!(cpu.registers()[2], 3);
+    assert_eq!(cpu.registers()[15], 0x0104);
+}
+
+#[test]
+fn repeated_swi_reti_round_trips() {
+    let mut cpu = boot_with_handler(&[SWI, NOP, SWI, NOP, SWI, ldi(1), HLT], &[RETI]);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[0], 1);
+    assert_eq!(cpu.psw() & PSW_S, 0);
+}
+
+/// `boot`, with a hardware interrupt handler at 0000:0200 that counts in R8.
+fn boot_with_irq_handler(program: &[u16]) -> Cpu {
+    let mut cpu = boot(program);
+    install_handler(&mut cpu, HW_INT_VECTOR, 0x0200, &[add_imm(8, 1), RETI]);
+    cpu.set_register(8, 0);
+    cpu
+}
+
+#[test]
+fn raised_interrupt_waits_for_psw_i_and_is_taken_once() {
+    let mut cpu = boot_with_irq_handler(&[NOP, NOP, NOP, HLT]);
+    cpu.raise_interrupt();
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0, "masked while PSW.I is clear");
+    cpu.set_psw(PSW_I);
+    cpu.step();
+    assert_ne!(cpu.psw() & PSW_S, 0);
+    assert_eq!(cpu.psw() & PSW_I, 0, "entry clears PSW.I");
+    assert_eq!(cpu.registers()[15], 0x0200);
+    assert_eq!(cpu.shadow_state()[2], PSW_I, "PSW' holds the interrupted PSW");
+    cpu.step();
+    cpu.step();
+    assert_eq!((cpu.psw(), cpu.registers()[15]), (PSW_I, 0x0101), "RETI restores PSW.I");
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[8], 1, "edge triggered: taken once");
+}
+
+#[test]
+fn irq_line_is_taken_until_it_drops() {
+    let mut cpu = boot_with_irq_handler(&[NOP, NOP, HLT]);
+    cpu.set_irq_line(true);
+    cpu.set_psw(PSW_I);
+    for _ in 0..3 {
+        cpu.step();
+        assert_eq!(cpu.registers()[15], 0x0200, "taken again while the line is high");
+        cpu.step();
+        cpu.step();
+    }
+    cpu.set_irq_line(false);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[8], 3);
+    assert_eq!(cpu.registers()[15], 0x0102);
+}
+
+#[test]
+fn irq_waits_while_a_handler_runs() {
+    // PSW.I set inside the handler does not let an interrupt into the
+    // shadow view.
+    let mut cpu = boot_with_irq_handler(&[NOP, HLT]);
+    install_handler(&mut cpu, HW_INT_VECTOR, 0x0200, &[NOP, add_imm(8, 1), RETI]);
+    cpu.set_psw(PSW_I);
+    cpu.raise_interrupt();
+    cpu.step();
+    cpu.raise_interrupt();
+    cpu.set_psw(PSW_S | PSW_I);
+    cpu.step();
+    cpu.step();
+    assert_eq!((cpu.registers()[15], cpu.registers()[8]), (0x0202, 1));
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0, "RETI");
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0200, "taken after RETI");
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[8], 2);
+}
+
+#[test]
+fn irq_raised_before_a_delay_slot_is_taken_at_the_branch_target() {
+    // JNZ to 0x0105 skips LDI 9; the interrupt arrives with its delay slot
+    // next and must not split the branch from its slot.
+    let mut cpu = boot_with_irq_handler(&[ldi(1), jnz(3), add_imm(9, 1), ldi(9), HLT, ldi(5), HLT]);
+    cpu.set_register(9, 0);
+    cpu.set_psw(PSW_I);
+    for _ in 0..2 { cpu.step(); }
+    cpu.raise_interrupt();
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0, "the delay slot runs first");
+    assert_eq!((cpu.registers()[9], cpu.registers()[15]), (1, 0x0105));
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0200);
+    run_to_halt(&mut cpu);
+    assert_eq!((cpu.registers()[0], cpu.registers()[8]), (5, 1));
+}
+
+/// `boot`, with an NMI handler at 0000:0300 that counts in R8.
+fn boot_with_nmi_handler(program: &[u16]) -> Cpu {
+    let mut cpu = boot(program);
+    install_handler(&mut cpu, NMI_VECTOR, 0x0300, &[add_imm(8, 1), RETI]);
+    cpu.set_register(8, 0);
+    cpu
+}
+
+#[test]
+fn nmi_is_taken_with_interrupts_disabled() {
+    let mut cpu = boot_with_nmi_handler(&[NOP, NOP, HLT]);
+    cpu.set_psw(PSW_C);
+    cpu.raise_nmi();
+    cpu.step();
+    assert_eq!(cpu.psw(), PSW_C | PSW_S, "PSW.I clear does not mask it");
+    assert_eq!(cpu.registers()[15], 0x0300);
+    assert_eq!(cpu.shadow_state()[2], PSW_C);
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[8], 1);
+    assert_eq!((cpu.psw(), cpu.registers()[15]), (PSW_C, 0x0102));
+}
+
+#[test]
+fn nmi_in_a_handler_abandons_it() {
+    // The SWI handler is cut short after its first ADD; the NMI handler's
+    // RETI returns straight to the program with the PSW from before SWI.
+    let mut cpu = boot_with_nmi_handler(&[SWI, ldi(7), HLT]);
+    install_handler(&mut cpu, SWI_VECTOR, 0x0200, &[add_imm(9, 1), add_imm(9, 1), RETI]);
+    cpu.set_register(9, 0);
+    cpu.set_psw(PSW_C | PSW_I);
+    cpu.step();
+    cpu.step();
+    cpu.raise_nmi();
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0300);
+    assert_eq!(cpu.shadow_state()[2], PSW_C | PSW_I, "PSW' still holds the normal PSW");
+    cpu.step();
+    cpu.step();
+    assert_eq!((cpu.psw(), cpu.registers()[15]), (PSW_C | PSW_I, 0x0101), "RETI");
+    run_to_halt(&mut cpu);
+    let r = cpu.registers();
+    assert_eq!((r[0], r[8], r[9]), (7, 1, 1));
+}
+
+#[test]
+fn second_nmi_waits_for_reti() {
+    let mut cpu = boot_with_nmi_handler(&[NOP, NOP, NOP, HLT]);
+    cpu.raise_nmi();
+    cpu.step();
+    cpu.raise_nmi();
+    cpu.step();
+    assert_eq!((cpu.registers()[15], cpu.registers()[8]), (0x0301, 1), "not re-entered");
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0, "RETI");
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0300, "taken after RETI");
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.registers()[8], 2);
+}
+
+#[test]
+fn nmi_waits_for_the_delay_slot() {
+    let mut cpu = boot_with_nmi_handler(&[ldi(1), jnz(3), add_imm(9, 1), ldi(9), HLT, ldi(5), HLT]);
+    cpu.set_register(9, 0);
+    cpu.step();
+    cpu.step();
+    cpu.raise_nmi();
+    cpu.step();
+    assert_eq!(cpu.psw() & PSW_S, 0, "the delay slot runs first");
+    assert_eq!((cpu.registers()[9], cpu.registers()[15]), (1, 0x0105));
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0300);
+    run_to_halt(&mut cpu);
+    assert_eq!((cpu.registers()[0], cpu.registers()[8]), (5, 1));
+}
+
+#[test]
+fn nmi_without_a_handler_halts() {
+    // The boot ROM points NMI_VECTOR at a HLT word at 0000:0005.
+    let mut cpu = boot(&[NOP, NOP, HLT]);
+    assert_eq!(cpu.memory()[NMI_VECTOR as usize], 0x0005);
+    cpu.raise_nmi();
+    run_to_halt(&mut cpu);
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.psw() & PSW_S, cpu.registers()[15]), (PSW_S, 0x0005));
+    assert_eq!(cpu.normal_state()[0], 0x0100, "interrupted before the program's first word");
+}
+
+#[test]
+fn last_event_code_names_interrupt_entries_and_reti() {
+    let mut cpu = boot_with_handler(&[SETI, SWI, NOP, NOP, NOP, HLT], &[RETI]);
+    install_handler(&mut cpu, HW_INT_VECTOR, 0x0200, &[RETI]);
+    install_handler(&mut cpu, NMI_VECTOR, 0x0200, &[RETI]);
+    let event = |cpu: &mut Cpu| {
+        cpu.step();
+        cpu.last_event_code()
+    };
+    assert_eq!(event(&mut cpu), SETI, "an instruction reports its own word");
+    assert_eq!(event(&mut cpu), EVENT_SWI);
+    assert_eq!(event(&mut cpu), EVENT_RETI);
+    cpu.raise_interrupt();
+    assert_eq!(event(&mut cpu), EVENT_IRQ);
+    assert_eq!(event(&mut cpu), EVENT_RETI);
+    cpu.raise_nmi();
+    assert_eq!(event(&mut cpu), EVENT_NMI);
+    assert_eq!(event(&mut cpu), EVENT_RETI);
+}
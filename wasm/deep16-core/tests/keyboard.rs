@@ -0,0 +1,60 @@
+//! Keyboard controller: polling, interrupt-driven input, the FIFO bound and
+//! the flush bit.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, Device, Keyboard, HW_INT_VECTOR, KEYBOARD_BASE, KEY_FIFO_LEN};
+
+/// Boots into `program` at 0000:0100 with ES at the I/O segment, R1 and R2
+/// holding the status and data register offsets, R5 the control offset.
+fn boot_keyboard(program: &[u16]) -> Cpu {
+    let mut cpu = boot_io(program);
+    cpu.set_register(1, 0x60);
+    cpu.set_register(2, 0x62);
+    cpu.set_register(5, 0x64);
+    cpu
+}
+
+#[test]
+fn polling_waits_for_a_key() {
+    let mut cpu = boot_keyboard(&[lds_es(3, 1), and_imm(3, 1), jz(-3), NOP, lds_es(4, 2), HLT]);
+    assert!(cpu.run(50), "no key yet, still polling");
+    assert!(cpu.push_key(0x41));
+    assert_eq!(cpu.read_word(KEYBOARD_BASE), 1, "data ready");
+    assert!(!cpu.run(50));
+    assert_eq!(cpu.registers()[4], 0x41);
+    assert_eq!(cpu.read_word(KEYBOARD_BASE), 0);
+}
+
+#[test]
+fn keys_interrupt_until_the_fifo_is_drained() {
+    let mut cpu = boot_keyboard(&[sts_es(6, 5), SETI, NOP, NOP, NOP, NOP, NOP, NOP, HLT]);
+    cpu.write_word(0x0200, lds_es(4, 2));
+    cpu.write_word(0x0201, add_imm(8, 1));
+    cpu.write_word(0x0202, RETI);
+    cpu.write_word(HW_INT_VECTOR as usize, 0x0200);
+    cpu.set_register(6, 1);
+    cpu.set_register(8, 0);
+    assert!(cpu.push_key(0x61));
+    assert!(cpu.push_key(0x62));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.registers()[8], 2, "one interrupt per key");
+    assert_eq!(cpu.registers()[4], 0x62);
+    assert_eq!(cpu.device::<Keyboard>().unwrap().pending(), 0);
+}
+
+#[test]
+fn fifo_overflow_and_flush() {
+    let mut kbd = Keyboard::default();
+    for code in 0..KEY_FIFO_LEN as u16 { assert!(kbd.push_key(code)); }
+    assert!(!kbd.push_key(0xFF));
+    assert_eq!(kbd.read(0), 0b11, "ready and overflowed");
+    assert_eq!(kbd.read(0), 0b01, "reading status clears overflow");
+    assert_eq!((kbd.read(2), kbd.read(2)), (0, 1));
+    kbd.write(4, 0b11);
+    assert_eq!((kbd.read(0), kbd.read(2), kbd.read(4)), (0, 0, 1), "flushed, interrupt still enabled");
+    assert!(!kbd.irq());
+    kbd.push_key(7);
+    assert!(kbd.irq());
+}
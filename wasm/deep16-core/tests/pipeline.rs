@@ -0,0 +1,189 @@
+//! FSH and the pipeline timing model: cycle counters per instruction class
+//! and the per-clock stage view.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, CycleCounters, SlotKind, StageSlot, StopReason, EX, ID, IF, MEM, WB};
+
+/// LD, a use of the loaded register, AMV (`MOV Rd, Rs, 3`), FSH, then an
+/// independent ALU op and a taken branch over LDI 9 with its delay slot.
+fn classes() -> Vec<u16> {
+    vec![
+        ldi(0x10),
+        ld(1, 0, 0),
+        add_imm(1, 1), // load-use stall
+        mov(2, 1) | 3, // AMV stall
+        FSH,           // two bubbles
+        add_imm(3, 1),
+        jnz(2), // taken, no extra cost
+        NOP,    // delay slot
+        ldi(9),
+        HLT,
+    ]
+}
+
+#[test]
+fn each_instruction_class_is_charged_its_cost() {
+    let mut cpu = boot(&classes());
+    cpu.write_word(0x10, 5);
+    cpu.set_register(3, 0);
+    cpu.set_timing(true);
+    let mut per_step = vec![];
+    while cpu.step() { per_step.push(cpu.cycle_counters().last_step_cycles); }
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.registers()[0], cpu.registers()[15]), (0x10, 0x0109), "the branch skipped LDI 9");
+    assert_eq!(per_step, [4 + 1, 1, 1 + 1, 1 + 1, 1 + 2, 1, 1, 1]);
+    assert_eq!(cpu.cycle_counters(), CycleCounters {
+        cycles: 16,
+        instructions: 8,
+        stalls: 2,
+        bubbles: 2,
+        flushes: 1,
+        last_step_cycles: 0,
+    });
+}
+
+#[test]
+fn counters_start_over_after_a_reset() {
+    let mut cpu = boot(&[FSH, HLT]);
+    cpu.set_timing(true);
+    assert!(!cpu.run(10));
+    assert_ne!(cpu.cycle_counters(), CycleCounters::default());
+    cpu.reset_cycle_counters();
+    assert_eq!(cpu.cycle_counters(), CycleCounters::default());
+    assert!(cpu.timing_enabled());
+
+    assert!(cpu.load_program(ENTRY as usize, &[FSH, HLT]));
+    run_boot_rom(&mut cpu);
+    assert!(!cpu.run(10));
+    assert_ne!(cpu.cycle_counters(), CycleCounters::default());
+    cpu.reset();
+    assert_eq!(cpu.cycle_counters(), CycleCounters::default());
+    assert!(cpu.timing_enabled(), "reset keeps the timing mode");
+}
+
+#[test]
+fn counters_stay_at_zero_with_timing_off() {
+    let mut cpu = boot(&classes());
+    assert!(!cpu.run(20));
+    assert_eq!(cpu.cycle_counters(), CycleCounters::default());
+}
+
+#[test]
+fn fsh_is_a_flush_outside_a_delay_slot() {
+    let mut cpu = boot(&[FSH, ldi(2), HLT]);
+    cpu.set_timing(true);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.registers()[0], 2);
+    let n = cpu.cycle_counters();
+    assert_eq!((n.instructions, n.flushes, n.bubbles), (2, 1, 2));
+    assert_eq!(n.cycles, 4 + 2 + 2, "fill, two issues, two bubbles");
+}
+
+#[test]
+fn fsh_in_a_delay_slot_discards_the_branch() {
+    // JNZ to 0x0105 is dropped: execution falls through to LDI 9.
+    let mut cpu = boot(&[ldi(1), jnz(3), FSH, ldi(9), HLT, ldi(5), HLT]);
+    cpu.set_timing(true);
+    cpu.step();
+    cpu.step();
+    cpu.step();
+    assert_eq!(cpu.registers()[15], 0x0103);
+    assert_eq!(cpu.cycle_counters().last_step_cycles, 1 + 2);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.registers()[0], cpu.registers()[15]), (9, 0x0104));
+    assert_eq!(cpu.cycle_counters(), CycleCounters {
+        cycles: 4 + 4 + 2,
+        instructions: 4,
+        stalls: 0,
+        bubbles: 2,
+        flushes: 1,
+        last_step_cycles: 0,
+    });
+}
+
+/// Cycle-steps `cpu` until the pipeline drains, collecting what entered IF
+/// on every clock.
+fn if_stream(cpu: &mut Cpu) -> Vec<(SlotKind, u16)> {
+    let mut seen = vec![];
+    while cpu.step_cycle() {
+        let s = cpu.pipeline_stages()[IF];
+        seen.push((s.kind, s.instr));
+        assert!(seen.len() < 50, "pipeline did not drain");
+    }
+    seen
+}
+
+#[test]
+fn jml_flushes_after_its_delay_slot() {
+    // JML R2 to 0000:0104 skips LDI 9 and HLT.
+    let program = [jml(2), ldi(0x20), ldi(9), HLT, st(0, 0, 0), add_imm(0, 1), HLT];
+    let mut cpu = boot(&program);
+    cpu.set_register(2, 0);
+    cpu.set_register(3, 0x0104);
+    let instr = |w| (SlotKind::Instr, w);
+    let bubble = (SlotKind::Flush, 0);
+    let empty = (SlotKind::Empty, 0);
+    assert_eq!(if_stream(&mut cpu), [
+        instr(program[0]),
+        instr(program[1]),
+        bubble,
+        bubble,
+        instr(program[4]),
+        instr(program[5]),
+        empty, empty, empty, empty,
+    ]);
+    assert_eq!(cpu.registers()[0], 0x21);
+    let n = cpu.cycle_counters();
+    assert_eq!((n.cycles, n.bubbles, n.flushes), (10, 2, 1), "the stage view and the counters agree");
+}
+
+#[test]
+fn conditional_branches_fill_the_delay_slot_without_bubbles() {
+    let program = [ldi(1), jnz(2), add_imm(0, 1), ldi(9), add_imm(0, 2), HLT];
+    let mut cpu = boot(&program);
+    let stream = if_stream(&mut cpu);
+    let fetched: Vec<u16> = stream.iter().filter(|s| s.0 == SlotKind::Instr).map(|s| s.1).collect();
+    assert_eq!(fetched, [program[0], program[1], program[2], program[4]], "LDI 9 skipped");
+    assert!(stream.iter().all(|s| s.0 != SlotKind::Flush));
+    assert_eq!(stream[3], (SlotKind::Instr, program[4]), "the target follows the delay slot directly");
+    assert_eq!(cpu.registers()[0], 4);
+}
+
+#[test]
+fn stages_carry_data_addresses_and_load_use_stalls() {
+    // ST, LD and LDS are memory instructions; the ADD after the LD waits a
+    // clock with a stall bubble in EX.
+    let program = [st(0, 1, 2), ld(4, 1, 2), add_imm(4, 1), lds_es(5, 1), HLT];
+    let mut cpu = boot(&program);
+    cpu.set_register(0, 0x1234);
+    cpu.set_register(1, 0x0040);
+    let mut clocks: Vec<[StageSlot; 5]> = vec![];
+    while cpu.step_cycle() { clocks.push(cpu.pipeline_stages()); }
+    let mem: Vec<(u16, Option<usize>)> = clocks.iter().map(|s| s[MEM]).filter(|s| s.kind == SlotKind::Instr).map(|s| (s.instr, s.mem_addr)).collect();
+    assert_eq!(mem, [
+        (program[0], Some(0x0042)),
+        (program[1], Some(0x0042)),
+        (program[2], None),
+        (program[3], Some(0x20040)),
+    ]);
+    let stall = clocks.iter().position(|s| s[EX].kind == SlotKind::Stall).expect("a stall bubble");
+    assert_eq!((clocks[stall][ID].instr, clocks[stall][MEM].instr), (program[2], program[1]), "ADD held in ID behind the LD");
+    assert_eq!(clocks[stall][IF].instr, program[3], "IF holds too");
+    assert_eq!(cpu.cycle_counters().stalls, 1);
+    assert_eq!(cpu.registers()[4], 0x1235);
+    assert!(clocks.last().unwrap()[WB].kind == SlotKind::Instr);
+}
+
+#[test]
+fn stages_show_the_word_that_was_executed() {
+    // The ST overwrites itself with R1; IF still shows the ST.
+    let mut cpu = boot(&[st(1, 2, 0), HLT]);
+    cpu.set_register(1, NOP);
+    cpu.set_register(2, ENTRY);
+    cpu.step_cycle();
+    assert_eq!(cpu.pipeline_stages()[IF].instr, st(1, 2, 0));
+    assert_eq!(cpu.memory()[ENTRY as usize], NOP);
+}
@@ -0,0 +1,85 @@
+//! PSW updates from NEG and the SET/CLR/SET2/CLR2 bit operations, and
+//! reading it back with LPSW.
+
+mod common;
+
+use common::*;
+use deep16_core::{IllegalPolicy, StopReason};
+
+/// Runs the single instruction `instr` from `psw` and returns the new PSW.
+fn psw_after(instr: u16, psw: u16) -> u16 {
+    let mut cpu = boot(&[instr, HLT]);
+    cpu.set_psw(psw);
+    assert!(cpu.step());
+    assert_eq!(cpu.stop_reason(), None);
+    cpu.psw()
+}
+
+#[test]
+fn neg_sets_flags_from_the_result() {
+    // C is the borrow out of 0 - Rx, so it is set for every non-zero operand.
+    for (v, result, flags) in [(0, 0, PSW_Z), (1, 0xFFFF, PSW_N | PSW_C), (0xFFFB, 5, PSW_C), (0x7FFF, 0x8001, PSW_N | PSW_C)] {
+        let mut cpu = boot(&[neg(3), HLT]);
+        cpu.set_register(3, v);
+        cpu.set_psw(PSW_N | PSW_Z | PSW_V | PSW_C | PSW_I);
+        assert!(cpu.step());
+        assert_eq!(cpu.registers()[3], result, "NEG {v:#06X}");
+        assert_eq!(cpu.psw(), flags | PSW_I, "NEG {v:#06X} flags; PSW.I is kept");
+    }
+}
+
+#[test]
+fn set_and_clr_address_every_psw_bit() {
+    // Starting from all ones, except S so the next fetch stays in the normal view.
+    let ones = !PSW_S;
+    for bit in 0..16 {
+        assert_eq!(psw_after(set(bit), 0), 1 << bit, "SET {bit}");
+        assert_eq!(psw_after(clr(bit), ones), ones & !(1 << bit), "CLR {bit}");
+    }
+}
+
+#[test]
+fn set2_and_clr2_address_bits_4_to_15() {
+    let ones = !PSW_S;
+    for imm in 0..12 {
+        assert_eq!(psw_after(set2(imm), 0), 1 << (imm + 4), "SET2 {imm}");
+        assert_eq!(psw_after(clr2(imm), ones), ones & !(1 << (imm + 4)), "CLR2 {imm}");
+    }
+    assert_eq!(psw_after(set2(0), 0), PSW_I, "SET2 0 is SETI");
+}
+
+#[test]
+fn set2_and_clr2_beyond_the_psw_are_illegal() {
+    for imm in 12..16 {
+        for instr in [set2(imm), clr2(imm)] {
+            assert_eq!(psw_after(instr, PSW_C), PSW_C, "ignored by default");
+
+            let mut cpu = boot(&[instr, HLT]);
+            cpu.set_illegal_policy(IllegalPolicy::Halt);
+            assert!(!cpu.step());
+            assert_eq!(cpu.stop_reason(), Some(StopReason::IllegalInstruction { addr: 0x0100, instr }));
+            assert_eq!(cpu.psw(), 0);
+        }
+    }
+}
+
+#[test]
+fn lpsw_reads_the_psw() {
+    let psw = PSW_C | PSW_I | 13 << 6;
+    let mut cpu = boot(&[lpsw(2), HLT]);
+    cpu.set_psw(psw);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.registers()[2], psw);
+    assert_eq!(cpu.psw(), psw, "flags untouched");
+}
+
+#[test]
+fn lpsw_in_a_handler_reads_the_live_psw() {
+    // Not PSW': the handler sees S set and I cleared by the interrupt entry.
+    let mut cpu = boot_with_handler(&[SWI, HLT], &[lpsw(1), RETI]);
+    cpu.set_psw(PSW_C | PSW_I);
+    assert!(!cpu.run(10));
+    assert_eq!(cpu.registers()[1], PSW_C | PSW_S);
+    assert_eq!(cpu.shadow_state()[2], PSW_C | PSW_I);
+    assert_eq!(cpu.psw(), PSW_C | PSW_I);
+}
@@ -0,0 +1,101 @@
+//! Text screen: stores from a program, text dumps, dirty spans, attributes,
+//! the cursor registers and snapshots.
+
+mod common;
+
+use common::*;
+use deep16_core::{
+    CellStyle, Cpu, Device, DirtySpan, Screen, VideoController, ATTR_BLINK, ATTR_INVERSE, SCREEN_BASE, VIDEO_BASE,
+    VIDEO_BLINK,
+};
+
+/// Boots into a program that writes "Hi" at row 2, column 3, the 'i' brown
+/// on cyan, then moves the cursor to column 5.
+fn boot_hi() -> Cpu {
+    let mut cpu = boot_io(&[sts_es(2, 1), add_imm(1, 1), sts_es(3, 1), sts_es(5, 4), HLT]);
+    for (r, v) in [(1, 0x1000 + 2 * 80 + 3), (2, b'H' as u16), (3, 0x1E00 | b'i' as u16), (4, 0x32), (5, 5)] {
+        cpu.set_register(r, v);
+    }
+    cpu
+}
+
+#[test]
+fn program_output_reads_back_as_text() {
+    let mut cpu = boot_hi();
+    assert_eq!(cpu.take_screen_dirty().len(), 25, "everything is new after power-on");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.screen_text(), format!("\n\n   Hi{}", "\n".repeat(22)));
+    assert_eq!(cpu.take_screen_dirty(), [DirtySpan { row: 2, start: 3, end: 5 }]);
+    assert!(cpu.take_screen_dirty().is_empty(), "taking the spans forgets them");
+    assert_eq!(cpu.read_word(SCREEN_BASE + 2 * 80 + 4), 0x1E69);
+    assert_eq!(cpu.device::<VideoController>().unwrap().cursor(), (5, 0));
+}
+
+#[test]
+fn only_changed_cells_are_dirty() {
+    let mut screen = Screen::default();
+    screen.take_dirty();
+    screen.write(81, 0);
+    assert!(screen.take_dirty().is_empty(), "storing the same value");
+    screen.write(90, 0x41);
+    screen.write(82, 0x42);
+    screen.write(1999, 0x0D);
+    assert_eq!(screen.take_dirty(), [
+        DirtySpan { row: 1, start: 2, end: 11 },
+        DirtySpan { row: 24, start: 79, end: 80 },
+    ]);
+    assert_eq!(screen.row_text(1).unwrap(), "  B       A");
+    assert_eq!(screen.row_text(24).unwrap(), "");
+    screen.write(0, 0x07);
+    assert_eq!(screen.row_text(0).unwrap(), "·");
+    screen.reset();
+    assert_eq!(screen.take_dirty().len(), 25);
+}
+
+#[test]
+fn cells_and_rows_past_the_edge_are_none() {
+    let mut screen = Screen::default();
+    screen.write(80, 0x41);
+    assert_eq!(screen.cell(0, 1), Some(0x41));
+    assert_eq!(screen.cell(79, 24), Some(0));
+    assert_eq!(screen.cell(80, 0), None, "does not wrap into the next row");
+    assert_eq!(screen.cell(0, 25), None);
+    assert_eq!(screen.row_text(25), None);
+    assert_eq!(screen.row_text(usize::MAX), None);
+}
+
+#[test]
+fn attributes_and_cursor_registers() {
+    let mut video = VideoController::default();
+    let plain = CellStyle { ch: b'A', fg: 7, bg: 0, blink: false };
+    assert_eq!(video.style(0x0041), plain, "attribute byte 0 takes the defaults");
+    let attr = (ATTR_BLINK | ATTR_INVERSE | 1 << 3 | 4) as u16;
+    assert_eq!(video.style(attr << 8 | 0x41), CellStyle { fg: 1, bg: 4, blink: true, ..plain });
+    video.write(0, 0);
+    assert!(!video.style(attr << 8 | 0x41).blink, "blinking disabled");
+    assert!(!video.cursor_visible());
+    video.write(6, 0x17);
+    assert_eq!(video.style(0x0041), CellStyle { fg: 7, bg: 2, ..plain });
+    video.write(2, 200);
+    video.write(4, 24);
+    assert_eq!(video.cursor(), (79, 24), "clamped to the screen");
+    video.write(0, 0xFFFF);
+    assert_eq!(video.read(0) & VIDEO_BLINK, VIDEO_BLINK);
+}
+
+#[test]
+fn screen_survives_snapshots() {
+    let mut cpu = boot_hi();
+    assert!(!cpu.run(100));
+    let saved = cpu.save_state();
+
+    let mut other = Cpu::new(1 << 20);
+    other.load_state(&saved).unwrap();
+    assert_eq!(other.screen_text(), cpu.screen_text());
+    assert_eq!(other.device::<VideoController>(), cpu.device::<VideoController>());
+    assert_eq!(other.read_word(VIDEO_BASE + 2), 5);
+
+    cpu.reset();
+    assert_eq!(cpu.screen_text(), "\n".repeat(24));
+    assert_eq!(cpu.device::<VideoController>(), Some(&VideoController::default()));
+}
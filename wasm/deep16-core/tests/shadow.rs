@@ -0,0 +1,129 @@
+//! The shadow context: SMV access to the alternate registers from either
+//! view, and the DS'/SS'/ES' segments a handler works with.
+
+mod common;
+
+use common::*;
+use deep16_core::{IllegalPolicy, StopReason};
+
+const ACS: u16 = 0;
+const ADS: u16 = 1;
+const APC: u16 = 4;
+const APSW: u16 = 5;
+
+#[test]
+fn smv_from_normal_view_reaches_the_shadow_registers() {
+    // shadow_state() order is PC', CS', PSW', DS', SS', ES'
+    let slot = [1, 3, 4, 5, 0, 2];
+    for sel in 0..6 {
+        let v = 0x0110 * (sel + 1);
+        let mut cpu = boot(&[ldi(v), smv_write(sel), ldi(0), smv_read(sel), HLT]);
+        let normal = cpu.segments();
+        assert!(!cpu.run(10));
+        assert_eq!(cpu.shadow_state()[slot[sel as usize]], v, "SMV write, selector {sel}");
+        assert_eq!(cpu.registers()[0], v, "SMV read, selector {sel}");
+        assert_eq!(cpu.segments(), normal, "the normal context is untouched");
+        assert_eq!(cpu.registers()[15], 0x0104, "stopped at HLT");
+    }
+}
+
+#[test]
+fn smv_from_shadow_view_reaches_the_normal_registers() {
+    // The handler reads the return PC, the normal DS and PSW', then sets the
+    // normal DS and moves the return address past LDI 9.
+    let mut cpu = boot_with_handler(
+        &[ldi(0x0ABC), mvs_set(1, 0), SWI, ldi(9), HLT, ldi(7), HLT],
+        &[
+            smv_read(APC), mov(1, 0),
+            smv_read(ADS), mov(2, 0),
+            smv_read(APSW), mov(3, 0),
+            smv_read(ACS), mov(4, 0),
+            ldi(0x0123), smv_write(ADS),
+            ldi(0x0105), smv_write(APC),
+            RETI,
+        ],
+    );
+    cpu.set_register(4, 0xFFFF);
+    assert!(!cpu.run(100));
+    let r = cpu.registers();
+    assert_eq!((r[1], r[2], r[3], r[4]), (0x0103, 0x0ABC, 0, 0));
+    assert_eq!(r[0], 7, "returned to the rewritten PC");
+    assert_eq!(cpu.segments()[1], 0x0123);
+    assert_eq!(cpu.shadow_state()[3], 0x0ABC, "the handler's own DS' is untouched");
+}
+
+#[test]
+fn apsw_in_a_handler_is_the_interrupted_psw() {
+    // In the handler APSW is the interrupted PSW; writing it changes what
+    // RETI restores.
+    let mut cpu = boot_with_handler(&[SWI, HLT], &[smv_read(APSW), mov(1, 0), ldi(PSW_C | PSW_Z), smv_write(APSW), RETI]);
+    cpu.set_psw(PSW_N);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.registers()[1], PSW_N);
+    assert_eq!(cpu.psw(), PSW_C | PSW_Z);
+    assert_eq!(cpu.shadow_state()[2], PSW_C | PSW_Z);
+}
+
+#[test]
+fn reserved_smv_selectors_are_illegal() {
+    for sel in 6..16 {
+        for instr in [smv_write(sel), smv_read(sel)] {
+            let mut cpu = boot(&[ldi(0x0042), instr, HLT]);
+            let shadow = cpu.shadow_state();
+            assert!(!cpu.run(10));
+            assert_eq!(cpu.stop_reason(), Some(StopReason::Halted), "ignored by default");
+            assert_eq!((cpu.registers()[0], cpu.shadow_state()), (0x0042, shadow));
+
+            let mut cpu = boot(&[ldi(0x0042), instr, HLT]);
+            cpu.set_illegal_policy(IllegalPolicy::Halt);
+            assert!(!cpu.run(10));
+            assert_eq!(cpu.stop_reason(), Some(StopReason::IllegalInstruction { addr: 0x0101, instr }));
+        }
+    }
+}
+
+#[test]
+fn handlers_use_their_own_data_segments() {
+    // The handler sees DS'/SS'/ES' as copies of the interrupted segments,
+    // moves all three and stores through each; the program then stores
+    // through its own, unchanged segments. SR = R13 makes R13 use SS.
+    let mut cpu = boot_with_handler(
+        &[
+            ldi(0x0300), mvs_set(1, 0), ldi(0x0400), mvs_set(2, 0), ldi(0x0500), mvs_set(3, 0), srs(13), SWI,
+            ldi(0x22), st(0, 5, 0), st(0, 13, 0), sts_es(0, 5), HLT,
+        ],
+        &[
+            mvs_get(1, 1), mvs_get(2, 2), mvs_get(3, 3),
+            ldi(0x0600), mvs_set(1, 0), ldi(0x0700), mvs_set(2, 0), ldi(0x0800), mvs_set(3, 0),
+            ldi(0x11), st(0, 5, 0), st(0, 13, 0), sts_es(0, 5),
+            RETI,
+        ],
+    );
+    cpu.set_register(5, 0);
+    cpu.set_register(13, 0);
+    while cpu.psw() & PSW_S == 0 { cpu.step(); }
+    assert_eq!(cpu.segments(), [0, 0x0300, 0x0400, 0x0500], "shadow segments start as copies");
+    assert_eq!(cpu.normal_state(), [0x0108, 0, 0x0300, 0x0400, 0x0500], "the interrupted context");
+    assert!(!cpu.run(100));
+    let r = cpu.registers();
+    assert_eq!((r[1], r[2], r[3]), (0x0300, 0x0400, 0x0500));
+    let m = cpu.memory();
+    assert_eq!((m[0x6000], m[0x7000], m[0x8000]), (0x11, 0x11, 0x11), "handler stores used DS'/SS'/ES'");
+    assert_eq!((m[0x3000], m[0x4000], m[0x5000]), (0x22, 0x22, 0x22), "program stores used DS/SS/ES");
+    assert_eq!(cpu.segments(), [0, 0x0300, 0x0400, 0x0500]);
+    assert_eq!(cpu.shadow_state()[3..], [0x0600, 0x0700, 0x0800]);
+}
+
+#[test]
+fn set_register_15_writes_the_active_pc() {
+    let mut cpu = boot_with_handler(&[SWI, HLT], &[ldi(1), HLT, ldi(2), HLT]);
+    cpu.set_register(15, 0x0100);
+    assert_eq!(cpu.registers()[15], 0x0100);
+    while cpu.psw() & PSW_S == 0 { cpu.step(); }
+    cpu.set_register(15, 0x0202);
+    assert_eq!(cpu.registers()[15], 0x0202, "R15 is PC' in the shadow view");
+    assert_eq!(cpu.normal_state()[0], 0x0101, "the interrupted PC is untouched");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!((cpu.registers()[0], cpu.registers()[15]), (2, 0x0203));
+}
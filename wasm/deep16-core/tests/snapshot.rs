@@ -0,0 +1,75 @@
+//! Snapshot save/restore: exact round trips (also from inside a branch delay
+//! slot and an interrupt handler), compression and rejected input.
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, SnapshotError, StopReason};
+
+fn finish(cpu: &mut Cpu) -> ([u16; 16], u16, [u16; 4], [u16; 6], Vec<u16>) {
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    (cpu.registers(), cpu.psw(), cpu.segments(), cpu.shadow_state(), cpu.memory().to_vec())
+}
+
+#[test]
+fn restore_resumes_identically_from_every_step() {
+    for steps in 0..7 {
+        let mut cpu = boot_swi();
+        for _ in 0..steps { cpu.step(); }
+        let saved = cpu.save_state();
+        let expected = finish(&mut cpu);
+        let mut other = Cpu::new(1 << 20);
+        assert_eq!(other.load_state(&saved), Ok(()));
+        assert_eq!(finish(&mut other), expected, "resumed after {steps} steps");
+        assert_eq!(other.save_state(), cpu.save_state());
+    }
+}
+
+#[test]
+fn memory_is_run_length_compressed() {
+    let cpu = boot_swi();
+    let saved = cpu.save_state();
+    assert_eq!(&saved[..6], b"D16S\x01\x00");
+    assert!(saved.len() < 512, "snapshot of a fresh machine is {} bytes", saved.len());
+}
+
+#[test]
+fn bad_snapshots_leave_the_machine_alone() {
+    let mut cpu = boot_swi();
+    cpu.step();
+    let saved = cpu.save_state();
+    let mut newer = saved.clone();
+    newer[4] = 2;
+    let mut junk = saved.clone();
+    junk.push(0);
+    let before = cpu.save_state();
+    assert_eq!(cpu.load_state(b"D16"), Err(SnapshotError::BadMagic));
+    assert_eq!(cpu.load_state(b"PK\x03\x04...."), Err(SnapshotError::BadMagic));
+    assert_eq!(cpu.load_state(&newer), Err(SnapshotError::UnsupportedVersion(2)));
+    assert_eq!(cpu.load_state(&saved[..saved.len() - 3]), Err(SnapshotError::Truncated));
+    assert_eq!(cpu.load_state(&junk), Err(SnapshotError::Corrupt("data after END chunk")));
+    assert_eq!(cpu.load_state(&saved[..8]), Err(SnapshotError::Truncated));
+    let mut no_cpu = saved[..8].to_vec();
+    no_cpu.extend_from_slice(b"END \0\0\0\0");
+    assert_eq!(cpu.load_state(&no_cpu), Err(SnapshotError::MissingChunk("CPU")));
+    assert_eq!(cpu.save_state(), before);
+}
+
+#[test]
+fn snapshots_only_load_into_the_same_memory_size() {
+    let saved = boot_swi().save_state();
+    let size_differs = Err(SnapshotError::Corrupt("memory size differs from this machine"));
+    for words in [16, (1 << 20) - 1, (1 << 20) + 1] {
+        let mut other = Cpu::new(words);
+        let before = other.save_state();
+        assert_eq!(other.load_state(&saved), size_differs, "{words} words");
+        assert_eq!(other.save_state(), before);
+    }
+
+    // A forged word count is refused before anything is allocated for it
+    let at = saved.windows(4).position(|w| w == b"MEM ").unwrap() + 8;
+    let mut forged = saved.clone();
+    forged[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(Cpu::new(1 << 20).load_state(&forged), size_differs);
+}
@@ -0,0 +1,71 @@
+//! Undo journal: `step_back` rewinds instructions, stores, branch delay slots
+//! and interrupt entries exactly.
+
+mod common;
+
+use common::*;
+use deep16_core::{BreakAt, Cpu, StopReason};
+
+/// Everything visible from outside, plus the low memory the programs touch.
+type View = ([u16; 16], u16, [u16; 4], [u16; 6], Vec<u16>);
+
+fn view(cpu: &Cpu) -> View {
+    (cpu.registers(), cpu.psw(), cpu.segments(), cpu.shadow_state(), cpu.memory()[..0x300].to_vec())
+}
+
+#[test]
+fn step_back_retraces_every_step() {
+    let mut cpu = boot_swi();
+    let mut seen = vec![view(&cpu)];
+    while cpu.step() { seen.push(view(&cpu)); }
+    assert_eq!(cpu.stop_reason(), Some(StopReason::Halted));
+    assert_eq!(cpu.registers()[0], 9);
+    assert_eq!(cpu.memory()[2], 1, "the SWI handler ran");
+    let end = view(&cpu);
+    // the final HLT stops without changing anything, so it is not journaled
+    assert_eq!(cpu.history_len(), seen.len() - 1);
+    while let Some(expected) = seen.pop() {
+        assert_eq!(view(&cpu), expected);
+        if seen.is_empty() { break; }
+        assert_eq!(cpu.step_back(1), 1);
+    }
+    assert_eq!(cpu.step_back(1), 0);
+    assert!(!cpu.run(100));
+    assert_eq!(view(&cpu), end, "replay after rewinding ends in the same state");
+}
+
+#[test]
+fn step_back_over_several_steps_at_once() {
+    let mut cpu = boot_swi();
+    for _ in 0..4 { cpu.step(); }
+    let mid = view(&cpu);
+    assert!(!cpu.run(100));
+    let undo = cpu.history_len() as u32 - 4;
+    assert_eq!(cpu.step_back(undo), undo);
+    assert_eq!(view(&cpu), mid);
+    assert!(!cpu.is_running());
+    assert_eq!(cpu.stop_reason(), None);
+}
+
+#[test]
+fn breakpoint_stops_are_not_journaled() {
+    let mut cpu = boot_swi();
+    cpu.add_breakpoint(BreakAt::Phys(0x0102));
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.history_len(), 2);
+    assert_eq!(cpu.step_back(1), 1);
+    assert_eq!(cpu.registers()[15], 0x0101);
+}
+
+#[test]
+fn history_limit_drops_oldest_steps() {
+    let mut cpu = boot_swi();
+    cpu.set_history_limit(3);
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.history_len(), 3);
+    assert_eq!(cpu.step_back(10), 3);
+    cpu.set_history_limit(0);
+    cpu.step();
+    assert_eq!(cpu.history_len(), 0);
+    assert_eq!(cpu.step_back(1), 0);
+}
@@ -0,0 +1,104 @@
+//! Interval timer: counting, prescaling, one-shot and periodic expiry,
+//! periodic interrupts and snapshots.
+
+mod common;
+
+use common::*;
+use deep16_core::{
+    Cpu, Device, Timer, HW_INT_VECTOR, TIMER_BASE, TIMER_EXPIRED, TIMER_IRQ, TIMER_LOAD, TIMER_PERIODIC, TIMER_RUN,
+};
+
+fn timer(reload: u16, prescaler: u16, control: u16) -> Timer {
+    let mut t = Timer::default();
+    t.write(4, reload);
+    t.write(6, prescaler);
+    t.write(0, control | TIMER_LOAD);
+    t
+}
+
+#[test]
+fn one_shot_counts_down_and_stops() {
+    let mut t = timer(3, 0, TIMER_RUN);
+    assert_eq!(t.value(), 3);
+    t.tick(2);
+    assert_eq!((t.value(), t.control() & TIMER_EXPIRED), (1, 0));
+    t.tick(5);
+    assert_eq!(t.value(), 0);
+    assert_eq!(t.control() & (TIMER_RUN | TIMER_EXPIRED), TIMER_EXPIRED, "stopped once expired");
+    assert!(!t.irq(), "interrupt not enabled");
+    t.write(0, TIMER_EXPIRED);
+    assert_eq!(t.control(), 0, "writing 1 clears the expired flag");
+}
+
+#[test]
+fn periodic_reloads_and_prescales() {
+    let mut t = timer(2, 3, TIMER_RUN | TIMER_PERIODIC | TIMER_IRQ);
+    t.tick(7);
+    assert_eq!(t.value(), 1, "one step per four clocks");
+    assert!(!t.irq());
+    t.tick(1);
+    assert_eq!(t.value(), 2, "reloaded");
+    assert!(t.irq());
+    t.write(0, TIMER_RUN | TIMER_PERIODIC | TIMER_IRQ);
+    assert!(t.irq(), "writing 0 leaves the flag alone");
+    t.write(0, TIMER_RUN | TIMER_PERIODIC | TIMER_IRQ | TIMER_EXPIRED);
+    assert!(!t.irq());
+    t.tick(16);
+    assert_eq!(t.value(), 2);
+    assert_ne!(t.control() & TIMER_EXPIRED, 0);
+}
+
+/// Boots into a program that starts the timer as a periodic interrupt source
+/// and then counts R9 down from 40; the handler counts interrupts in R8.
+fn boot_ticking() -> Cpu {
+    let mut cpu = Cpu::new(1 << 20);
+    cpu.load_program(0x0100, &[
+        sts_es(1, 5), // reload
+        sts_es(2, 6), // control
+        SETI,
+        sub_imm(9, 1),
+        jnz(-2),
+        NOP,
+        HLT,
+    ]);
+    cpu.load_program(0x0200, &[sts_es(3, 6), add_imm(8, 1), RETI]);
+    run_boot_rom(&mut cpu);
+    cpu.write_word(HW_INT_VECTOR as usize, 0x0200);
+    cpu.set_segments(0, 0, 0, 0xF000);
+    let control = TIMER_RUN | TIMER_IRQ | TIMER_PERIODIC;
+    for (r, v) in [(1, 10), (2, control | TIMER_LOAD), (3, control | TIMER_EXPIRED), (5, 0x24), (6, 0x20), (8, 0), (9, 40)] {
+        cpu.set_register(r, v);
+    }
+    cpu
+}
+
+#[test]
+fn periodic_interrupts_preempt_a_loop() {
+    let mut cpu = boot_ticking();
+    assert!(!cpu.run(1000));
+    assert_eq!(cpu.registers()[9], 0);
+    // 3 setup steps, 120 loop steps and 4 per interrupt, 10 steps apart
+    assert_eq!(cpu.registers()[8], 19);
+    let t = cpu.device::<Timer>().unwrap();
+    assert_eq!(t.control() & TIMER_RUN, TIMER_RUN);
+    assert_eq!(cpu.read_word(TIMER_BASE + 4), 10);
+}
+
+#[test]
+fn timer_state_survives_snapshots() {
+    let mut cpu = boot_ticking();
+    cpu.run(57);
+    let saved = cpu.save_state();
+    let timer = cpu.device::<Timer>().unwrap().clone();
+    assert!(!cpu.run(1000));
+
+    let mut other = Cpu::new(1 << 20);
+    other.load_state(&saved).unwrap();
+    assert_eq!(other.device::<Timer>(), Some(&timer));
+    assert!(!other.run(1000));
+    assert_eq!(other.registers(), cpu.registers());
+    assert_eq!(other.device::<Timer>(), cpu.device::<Timer>());
+
+    cpu.reset();
+    assert_eq!(cpu.device::<Timer>(), Some(&Timer::default()));
+}
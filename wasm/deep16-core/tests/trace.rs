@@ -0,0 +1,97 @@
+//! Execution trace: recorded deltas, filters, the ring bound and both export
+//! formats, plus disassembly.
+
+mod common;
+
+use common::*;
+use deep16_core::{disassemble, trace_to_binary, trace_to_text, Cpu, InstrClass, RegDelta, TraceFilter, TraceMem};
+
+/// Boots into `program` at 0000:0100 with tracing on.
+fn boot_traced(program: &[u16]) -> Cpu {
+    let mut cpu = boot(program);
+    cpu.set_trace(64);
+    cpu
+}
+
+/// Counts R0 down from 2, storing it each time; R1 copies it in the delay slot.
+const LOOP: [u16; 6] = [0x0002, 0xC301, 0xA000, 0xE3FD, 0xF840, HLT];
+
+#[test]
+fn records_register_memory_and_flag_changes() {
+    assert_eq!(LOOP[..5], [ldi(2), sub_imm(0, 1), st(0, 0, 0), jnz(-3), mov(1, 0)]);
+    let mut cpu = boot_traced(&LOOP);
+    cpu.set_register(1, 0);
+    assert!(!cpu.run(100));
+    let trace = cpu.take_trace();
+    assert_eq!(cpu.trace_len(), 0);
+    let pcs: Vec<u16> = trace.iter().map(|e| e.pc).collect();
+    assert_eq!(pcs, [0x100, 0x101, 0x102, 0x103, 0x104, 0x101, 0x102, 0x103, 0x104]);
+    assert_eq!(trace.iter().map(|e| e.seq).collect::<Vec<_>>(), (0..9).collect::<Vec<_>>());
+    let sub = &trace[5];
+    assert_eq!((sub.cs, sub.addr, sub.instr), (0, 0x101, sub_imm(0, 1)));
+    assert_eq!(sub.regs, [RegDelta { reg: 0, old: 1, new: 0 }]);
+    assert_eq!((sub.psw_before & 0x2, sub.psw_after & 0x2), (0, 0x2), "Z set");
+    assert_eq!(trace[6].mem, Some(TraceMem { addr: 0, value: 0, is_store: true }));
+    assert!(trace[6].regs.is_empty());
+
+    let text = trace_to_text(&trace);
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 9);
+    assert_eq!(lines[5], "       5 0000:0101 00101 C301  SUB R0, #0x1           R0 0001->0000  +Z");
+    assert_eq!(lines[6], "       6 0000:0102 00102 A000  ST R0, [R0+0x0]        [00000]<-0000");
+    assert_eq!(lines[7], "       7 0000:0103 00103 E3FD  JNZ -3   ; 0x0101");
+}
+
+#[test]
+fn filter_by_class_and_address() {
+    let mut cpu = boot_traced(&LOOP);
+    let classes = InstrClass::Mem.bit() | InstrClass::Mov.bit();
+    cpu.set_trace_filter(TraceFilter { start: 0x102, end: 0x104, classes });
+    assert!(!cpu.run(100));
+    let trace = cpu.take_trace();
+    let got: Vec<(u64, u16)> = trace.iter().map(|e| (e.seq, e.pc)).collect();
+    assert_eq!(got, [(2, 0x102), (4, 0x104), (6, 0x102), (8, 0x104)]);
+}
+
+#[test]
+fn ring_keeps_the_newest_entries() {
+    let mut cpu = boot_traced(&LOOP);
+    cpu.set_trace(3);
+    assert!(!cpu.run(100));
+    let seqs: Vec<u64> = cpu.take_trace().iter().map(|e| e.seq).collect();
+    assert_eq!(seqs, [6, 7, 8]);
+    cpu.set_trace(0);
+    cpu.reset();
+    cpu.run(5);
+    assert_eq!(cpu.trace_len(), 0);
+}
+
+#[test]
+fn binary_export_is_compact() {
+    let mut cpu = boot_traced(&LOOP);
+    assert!(!cpu.run(100));
+    let trace = cpu.take_trace();
+    let bin = trace_to_binary(&trace);
+    assert_eq!(&bin[..6], b"D16T\x01\x00");
+    // first entry: LDI 2 -> R0, seq delta 0, no PSW change (Z, N stay clear)
+    assert_eq!(&bin[6..20], &[0, 0, 1, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00]);
+    assert!(bin.len() < trace.len() * 24);
+}
+
+#[test]
+fn disassembly_matches_the_js_syntax() {
+    assert_eq!(disassemble(0x002A), "LDI #0x002A");
+    assert_eq!(disassemble(0x8A43), "LD R5, [R2+0x3]");
+    assert_eq!(disassemble(0xC012), "ADD R1, R2");
+    assert_eq!(disassemble(0xFBC8), "JMP R2");
+    assert_eq!(disassemble(0xFBBE), "LINK");
+    assert_eq!(disassemble(0xF83C), "MOV R0, PC");
+    assert_eq!(disassemble(0xFC3F), "LSI R1, #-0x1");
+    assert_eq!(disassemble(0xFF45), "MVS DS, R1");
+    assert_eq!(disassemble(0xFFC4), "SMV APC");
+    assert_eq!(disassemble(0xFFD5), "SMV R0, APSW");
+    assert_eq!(disassemble(0xFFE3), "LPSW R3");
+    assert_eq!(disassemble(0xFFF3), "RETI");
+    assert_eq!(disassemble(0xFF80), "??? (0xFF80)");
+    assert_eq!(disassemble(0xFFFF), "HLT");
+}
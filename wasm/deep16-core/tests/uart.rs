@@ -0,0 +1,90 @@
+//! Serial port: polled echo, output callbacks, receive interrupts and the
+//! register view.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod common;
+
+use common::*;
+use deep16_core::{Cpu, Device, Uart, HW_INT_VECTOR, UART_BASE, UART_RX_READY, UART_TX_READY};
+
+/// Echoes received bytes until it has echoed a newline.
+const ECHO: [u16; 10] = [
+    0xF331, // LDS R3, ES, R1   status
+    0xC731, // AND R3, #1
+    0xE1FD, // JZ -3
+    NOP,
+    0xF342, // LDS R4, ES, R2   data
+    0xF742, // STS R4, ES, R2
+    0xC34A, // SUB R4, #10
+    0xE3F8, // JNZ -8
+    NOP,
+    HLT,
+];
+
+/// Boots into `program` at 0000:0100 with ES at the I/O segment and R1/R2
+/// holding the status and data register offsets.
+fn boot_uart(program: &[u16]) -> Cpu {
+    let mut cpu = boot_io(program);
+    cpu.set_register(1, 0x40);
+    cpu.set_register(2, 0x42);
+    cpu
+}
+
+#[test]
+fn polled_echo() {
+    assert_eq!(ECHO[..8], [lds_es(3, 1), and_imm(3, 1), jz(-3), NOP, lds_es(4, 2), sts_es(4, 2), sub_imm(4, 10), jnz(-8)]);
+    let mut cpu = boot_uart(&ECHO);
+    assert!(cpu.run(100), "waiting for input");
+    assert!(cpu.uart_read_output().is_empty());
+    cpu.uart_write_input(b"hi\nleft");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.uart_read_output(), b"hi\n");
+    assert!(cpu.uart_read_output().is_empty(), "reading drains the output");
+    assert_eq!(cpu.device::<Uart>().unwrap().pending_input(), 4);
+}
+
+#[test]
+fn output_callback_sees_each_byte() {
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut cpu = boot_uart(&ECHO);
+    let sink = seen.clone();
+    cpu.set_uart_output_callback(move |b| sink.borrow_mut().push(b));
+    cpu.uart_write_input(b"ok\n");
+    assert!(!cpu.run(100));
+    assert_eq!(*seen.borrow(), b"ok\n");
+    assert!(cpu.uart_read_output().is_empty());
+}
+
+#[test]
+fn receive_interrupts() {
+    // The handler sends each received byte back incremented.
+    let mut cpu = boot_uart(&[sts_es(5, 1), SETI, NOP, NOP, NOP, NOP, NOP, NOP, NOP, NOP, HLT]);
+    cpu.write_word(0x0200, lds_es(4, 2));
+    cpu.write_word(0x0201, add_imm(4, 1));
+    cpu.write_word(0x0202, sts_es(4, 2));
+    cpu.write_word(0x0203, RETI);
+    cpu.write_word(HW_INT_VECTOR as usize, 0x0200);
+    cpu.set_register(5, UART_RX_READY);
+    cpu.uart_write_input(b"HAL");
+    assert!(!cpu.run(100));
+    assert_eq!(cpu.uart_read_output(), b"IBM");
+    assert_eq!(cpu.read_word(UART_BASE), UART_TX_READY | UART_RX_READY << 8);
+}
+
+#[test]
+fn registers_and_transmit_interrupt() {
+    let mut uart = Uart::default();
+    assert_eq!(uart.read(0), UART_TX_READY);
+    assert!(!uart.irq());
+    uart.write(0, UART_TX_READY);
+    assert!(uart.irq(), "TX is always ready");
+    uart.write(0, 0);
+    uart.write(4, 12);
+    uart.write_input(&[0x41]);
+    assert_eq!((uart.read(0), uart.read(4)), (UART_RX_READY | UART_TX_READY, 12));
+    assert_eq!((uart.peek(2), uart.read(2), uart.read(2)), (0x41, 0x41, 0));
+    uart.write(2, 0x1234);
+    assert_eq!(uart.read_output(), [0x34]);
+}
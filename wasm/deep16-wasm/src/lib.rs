@@ -1,744 +1,649 @@
+use std::cell::RefCell;
+
+use deep16_core::{
+    disassemble_at, trace_to_binary, trace_to_text, BreakAt, Cpu, Device, IllegalPolicy, Screen, SlotKind, StopReason,
+    TraceFilter, VideoController, WatchKind, SCREEN_LEN,
+};
 use wasm_bindgen::prelude::*;
 
-struct Cpu {
-    mem: Vec<u16>,
-    reg: [u16; 16],
-    psw: u16,
-    spsw: u16,
-    cs: u16,
-    scs: u16,
-    spc: u16,
-    ds: u16,
-    ss: u16,
-    es: u16,
-    running: bool,
-    delay_active: bool,
-    delayed_pc: u16,
-    delayed_cs: u16,
-    delayed_to_shadow: bool,
-    branch_taken: bool,
-    last_alu_result: i32,
-    last_op_alu: bool,
-    recent_addr: usize,
-    recent_base: u16,
-    recent_offset: u16,
-    recent_seg_val: u16,
-    recent_seg_idx: u16,
-    recent_is_store: bool,
-    last_event_code: u16,
-    last_event_spc: u16,
-    last_event_scs: u16,
-}
-
-static mut CPU: Option<Cpu> = None;
-
-impl Cpu {
-    fn new(mem_words: usize) -> Cpu {
-        let mut reg = [0u16; 16];
-        reg[13] = 0x7FFF;
-        reg[15] = 0x0000;
-        Cpu {
-            mem: vec![0xFFFF; mem_words],
-            reg,
-            psw: 0,
-            spsw: 0,
-            cs: 0xFFFF,
-            scs: 0,
-            spc: 0,
-            ds: 0x1000,
-            ss: 0x8000,
-            es: 0x2000,
-            running: false,
-            delay_active: false,
-            delayed_pc: 0,
-            delayed_cs: 0,
-            delayed_to_shadow: false,
-            branch_taken: false,
-            last_alu_result: 0,
-            last_op_alu: false,
-            recent_addr: 0,
-            recent_base: 0,
-            recent_offset: 0,
-            recent_seg_val: 0,
-            recent_seg_idx: 0,
-            recent_is_store: false,
-            last_event_code: 0,
-            last_event_spc: 0,
-            last_event_scs: 0,
+/// Default memory size for the implicit machine behind the free functions.
+const DEFAULT_MEM_WORDS: usize = 1 << 20;
+
+/// One independent Deep16 machine. JS can create as many of these as it likes
+/// (e.g. a reference run next to a student run) without them sharing state.
+#[wasm_bindgen]
+pub struct Deep16Machine {
+    cpu: Cpu,
+}
+
+#[wasm_bindgen]
+impl Deep16Machine {
+    #[wasm_bindgen(constructor)]
+    pub fn new(mem_words: usize) -> Deep16Machine {
+        Deep16Machine { cpu: Cpu::new(mem_words) }
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+    }
+
+    pub fn set_segments(&mut self, cs: u16, ds: u16, ss: u16, es: u16) {
+        self.cpu.set_segments(cs, ds, ss, es);
+    }
+
+    /// Copies `data` to physical address `ptr` and points execution back at
+    /// the boot ROM. Returns `false` (and loads nothing) if it does not fit.
+    pub fn load_program(&mut self, ptr: usize, data: &[u16]) -> bool {
+        self.cpu.load_program(ptr, data)
+    }
+
+    pub fn get_registers(&self) -> Box<[u16]> {
+        self.cpu.registers().into()
+    }
+
+    pub fn get_psw(&self) -> u16 {
+        self.cpu.psw()
+    }
+
+    pub fn get_segments(&self) -> Box<[u16]> {
+        self.cpu.segments().into()
+    }
+
+    /// Words as a program would load them, device registers included.
+    pub fn get_memory_slice(&self, start: usize, count: usize) -> Box<[u16]> {
+        let end = start.saturating_add(count).min(self.cpu.memory().len());
+        let start = start.min(end);
+        self.cpu.read_words(start, end - start).into()
+    }
+
+    pub fn get_memory_word(&self, addr: usize) -> u16 {
+        self.cpu.read_word(addr)
+    }
+
+    pub fn step(&mut self) -> bool {
+        self.cpu.step()
+    }
+
+    pub fn run_steps(&mut self, n: u32) -> bool {
+        self.cpu.run(n)
+    }
+
+    /// Snapshot of the whole machine (doc/Deep16-Snapshot.md) as bytes.
+    pub fn save_state(&self) -> Vec<u8> {
+        self.cpu.save_state()
+    }
+
+    /// Restores a snapshot from `save_state`. Throws with the reason if it
+    /// is rejected; the machine is unchanged then.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), JsError> {
+        self.cpu.load_state(data).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Keeps a trace of up to `capacity` instructions; 0 turns tracing off.
+    pub fn set_trace(&mut self, capacity: usize) {
+        self.cpu.set_trace(capacity);
+    }
+
+    /// Only traces instructions at physical addresses `start..=end` whose
+    /// class bit is set in `classes` (bit order of `InstrClass`: LDI, LD/ST,
+    /// ALU, jump, LDS/STS, MOV, LSI, SOP, MVS, SMV, LPSW, SYS, HLT, undefined).
+    pub fn set_trace_filter(&mut self, start: usize, end: usize, classes: u32) {
+        self.cpu.set_trace_filter(TraceFilter { start, end, classes });
+    }
+
+    pub fn get_trace_len(&self) -> usize {
+        self.cpu.trace_len()
+    }
+
+    /// Empties the trace buffer into the compact binary format.
+    pub fn take_trace_binary(&mut self) -> Vec<u8> {
+        trace_to_binary(&self.cpu.take_trace())
+    }
+
+    /// Empties the trace buffer into a text log with disassembly.
+    pub fn take_trace_text(&mut self) -> String {
+        trace_to_text(&self.cpu.take_trace())
+    }
+
+    /// Rewinds up to `n` steps; returns how many were undone.
+    pub fn step_back(&mut self, n: u32) -> u32 {
+        self.cpu.step_back(n)
+    }
+
+    /// Caps the undo history at `limit` steps; 0 turns it off.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.cpu.set_history_limit(limit);
+    }
+
+    pub fn get_history_len(&self) -> usize {
+        self.cpu.history_len()
+    }
+
+    /// `[code, addr, instr]` for why the machine last stopped. Codes: 0 still
+    /// running, 1 halted, 2 fetch from uninitialized memory, 3 physical
+    /// address out of range, 4 illegal instruction, 5 odd register pair,
+    /// 6 breakpoint, 7 watchpoint (for these two the third word is the id).
+    pub fn get_stop_reason(&self) -> Box<[u32]> {
+        let v = match self.cpu.stop_reason() {
+            None => [0, 0, 0],
+            Some(StopReason::Halted) => [1, 0, 0],
+            Some(StopReason::FetchFromUninitialized { addr }) => [2, addr as u32, 0],
+            Some(StopReason::PhysicalAddressOutOfRange { addr }) => [3, addr as u32, 0],
+            Some(StopReason::IllegalInstruction { addr, instr }) => [4, addr as u32, instr as u32],
+            Some(StopReason::OddRegisterPairViolation { addr, instr }) => [5, addr as u32, instr as u32],
+            Some(StopReason::Breakpoint { id, addr }) => [6, addr as u32, id],
+            Some(StopReason::Watchpoint { id, addr }) => [7, addr as u32, id],
+        };
+        v.into()
+    }
+
+    /// Human-readable stop reason, empty while running.
+    pub fn get_stop_message(&self) -> String {
+        self.cpu.stop_reason().map(|r| r.to_string()).unwrap_or_default()
+    }
+
+    /// What to do with undefined encodings: 0 ignore, 1 halt with a fault,
+    /// 2 trap to ILLEGAL_VECTOR. Other values are treated as 0.
+    pub fn set_illegal_policy(&mut self, mode: u8) {
+        let policy = match mode {
+            1 => IllegalPolicy::Halt,
+            2 => IllegalPolicy::Trap,
+            _ => IllegalPolicy::Ignore,
+        };
+        self.cpu.set_illegal_policy(policy);
+    }
+
+    /// Turns the pipeline timing model on or off.
+    pub fn set_timing_mode(&mut self, enabled: bool) {
+        self.cpu.set_timing(enabled);
+    }
+
+    /// `[cycles, instructions, stalls, bubbles, flushes, last_step_cycles]`.
+    pub fn get_cycle_counters(&self) -> Box<[f64]> {
+        let t = self.cpu.cycle_counters();
+        vec![
+            t.cycles as f64,
+            t.instructions as f64,
+            t.stalls as f64,
+            t.bubbles as f64,
+            t.flushes as f64,
+            t.last_step_cycles as f64,
+        ].into_boxed_slice()
+    }
+
+    pub fn reset_cycle_counters(&mut self) {
+        self.cpu.reset_cycle_counters();
+    }
+
+    /// Advances the pipeline view by one clock (turns the timing model on).
+    pub fn step_cycle(&mut self) -> bool {
+        self.cpu.step_cycle()
+    }
+
+    /// Stages IF, ID, EX, MEM, WB, six words each: `[kind, instr, pc, cs,
+    /// mem_addr, forwarding]`. Kind: 0 empty, 1 instruction, 2 stall bubble,
+    /// 3 flush bubble. `mem_addr` is 0xFFFFFFFF unless the instruction is a
+    /// load or store. Forwarding (EX only): bit 0 from EX/MEM, bit 1 from
+    /// MEM/WB.
+    pub fn get_pipeline_stages(&self) -> Box<[u32]> {
+        let mut v = Vec::with_capacity(30);
+        for s in self.cpu.pipeline_stages() {
+            let kind = match s.kind {
+                SlotKind::Empty => 0,
+                SlotKind::Instr => 1,
+                SlotKind::Stall => 2,
+                SlotKind::Flush => 3,
+            };
+            let fwd = (s.fwd_from_mem as u32) | ((s.fwd_from_wb as u32) << 1);
+            let mem_addr = s.mem_addr.map_or(u32::MAX, |a| a as u32);
+            v.extend_from_slice(&[kind, s.instr as u32, s.pc as u32, s.cs as u32, mem_addr, fwd]);
         }
+        v.into_boxed_slice()
+    }
+
+    /// Breakpoint on a physical address; returns its id.
+    pub fn add_breakpoint(&mut self, addr: usize) -> u32 {
+        self.cpu.add_breakpoint(BreakAt::Phys(addr))
+    }
+
+    /// Breakpoint on a CS:PC pair; returns its id.
+    pub fn add_breakpoint_cs_pc(&mut self, cs: u16, pc: u16) -> u32 {
+        self.cpu.add_breakpoint(BreakAt::CsPc(cs, pc))
+    }
+
+    /// Attaches a condition such as `R11 == 0x0234 && Z` to breakpoint `id`;
+    /// an empty string removes it. Throws with the parse error to show to the
+    /// user if it is rejected.
+    pub fn set_breakpoint_condition(&mut self, id: u32, condition: &str) -> Result<(), JsError> {
+        self.cpu.set_breakpoint_condition(id, condition).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Lets the next `ignore` hits of breakpoint `id` pass and restarts its
+    /// hit count.
+    pub fn set_breakpoint_ignore_count(&mut self, id: u32, ignore: u32) -> bool {
+        self.cpu.set_breakpoint_ignore_count(id, ignore)
+    }
+
+    /// Hits of breakpoint `id` with its condition true, or undefined.
+    pub fn get_breakpoint_hits(&self, id: u32) -> Option<u32> {
+        self.cpu.breakpoint_hits(id)
+    }
+
+    pub fn remove_breakpoint(&mut self, id: u32) -> bool {
+        self.cpu.remove_breakpoint(id)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.cpu.clear_breakpoints();
+    }
+
+    /// Watchpoint on physical addresses `start..=end`. Kind: 0 read, 1 write,
+    /// 2 any access. Returns its id.
+    pub fn add_watchpoint(&mut self, start: usize, end: usize, kind: u8) -> u32 {
+        let kind = match kind {
+            0 => WatchKind::Read,
+            1 => WatchKind::Write,
+            _ => WatchKind::Access,
+        };
+        self.cpu.add_watchpoint(start, end, kind)
+    }
+
+    pub fn remove_watchpoint(&mut self, id: u32) -> bool {
+        self.cpu.remove_watchpoint(id)
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.cpu.clear_watchpoints();
+    }
+
+    /// `[id, instr, instr_addr, cs, pc, addr, is_store, old, new, seg_idx,
+    /// seg_val]` for the last watchpoint stop, or empty if there was none.
+    pub fn get_watch_hit(&self) -> Box<[u32]> {
+        let Some(h) = self.cpu.watch_hit() else { return Box::new([]); };
+        vec![
+            h.id,
+            h.instr as u32,
+            h.instr_addr as u32,
+            h.cs as u32,
+            h.pc as u32,
+            h.addr as u32,
+            h.is_store as u32,
+            h.old_value as u32,
+            h.new_value as u32,
+            h.seg_idx as u32,
+            h.seg_val as u32,
+        ].into_boxed_slice()
+    }
+
+    pub fn raise_interrupt(&mut self) {
+        self.cpu.raise_interrupt();
+    }
+
+    pub fn set_irq_line(&mut self, level: bool) {
+        self.cpu.set_irq_line(level);
+    }
+
+    pub fn raise_nmi(&mut self) {
+        self.cpu.raise_nmi();
+    }
+
+    pub fn push_key(&mut self, code: u16) -> bool {
+        self.cpu.push_key(code)
+    }
+
+    pub fn uart_write_input(&mut self, bytes: &[u8]) {
+        self.cpu.uart_write_input(bytes);
+    }
+
+    pub fn uart_read_output(&mut self) -> Vec<u8> {
+        self.cpu.uart_read_output()
+    }
+
+    pub fn get_screen_text(&self) -> String {
+        self.cpu.screen_text()
+    }
+
+    pub fn get_screen_cells(&self, start: usize, count: usize) -> Box<[u16]> {
+        let Some(screen) = self.cpu.device::<Screen>() else { return Box::new([]); };
+        let end = start.saturating_add(count).min(SCREEN_LEN);
+        screen.cells()[start.min(end)..end].into()
+    }
+
+    pub fn get_screen_dirty(&mut self) -> Box<[u16]> {
+        self.cpu
+            .take_screen_dirty()
+            .iter()
+            .flat_map(|d| [d.row as u16, d.start as u16, d.end as u16])
+            .collect()
+    }
+
+    pub fn get_video_registers(&self) -> Box<[u16]> {
+        let Some(v) = self.cpu.device::<VideoController>() else { return Box::new([]); };
+        (0..4).map(|i| v.peek(i * 2)).collect()
+    }
+
+    pub fn get_recent_access(&self) -> Box<[u32]> {
+        let a = self.cpu.recent_access();
+        vec![
+            a.addr as u32,
+            a.base as u32,
+            a.offset as u32,
+            a.seg_val as u32,
+            a.seg_idx as u32,
+            if a.is_store { 1 } else { 0 },
+        ].into_boxed_slice()
+    }
+
+    pub fn get_last_event(&self) -> Box<[u16]> {
+        let [spc, scs, spsw, ..] = self.cpu.shadow_state();
+        vec![self.cpu.last_event_code(), spc, scs, self.cpu.psw(), spsw].into_boxed_slice()
+    }
+
+    pub fn get_shadow_state(&self) -> Box<[u16]> {
+        self.cpu.shadow_state().into()
+    }
+
+    pub fn get_normal_state(&self) -> Box<[u16]> {
+        self.cpu.normal_state().into()
     }
-    fn reset(&mut self) {
-        self.mem.fill(0xFFFF);
-        self.reg = [0u16; 16];
-        self.reg[13] = 0x7FFF;
-        self.reg[15] = 0x0000;
-        self.psw = 0;
-        self.spsw = 0;
-        self.cs = 0xFFFF;
-        self.scs = 0;
-        self.spc = 0;
-        self.ds = 0x1000;
-        self.ss = 0x8000;
-        self.es = 0x2000;
-        self.running = false;
-        self.delay_active = false;
-        self.delayed_pc = 0;
-        self.delayed_cs = 0;
-        self.delayed_to_shadow = false;
-        self.branch_taken = false;
-        self.last_alu_result = 0;
-        self.last_op_alu = false;
-        self.recent_addr = 0;
-        self.recent_base = 0;
-        self.recent_offset = 0;
-        self.recent_seg_val = 0;
-        self.recent_seg_idx = 0;
-        self.recent_is_store = false;
-        self.last_event_code = 0;
-        self.last_event_spc = 0;
-        self.last_event_scs = 0;
-    }
-}
-
-unsafe fn cpu_mut() -> &'static mut Cpu {
-    CPU.as_mut().expect("CPU not initialized")
-}
-unsafe fn cpu_ref() -> &'static Cpu {
-    CPU.as_ref().expect("CPU not initialized")
+}
+
+// Compatibility layer: the original free-function API operates on a default
+// machine that is created on first use, so calling before `init` is harmless.
+thread_local! {
+    static MACHINE: RefCell<Option<Deep16Machine>> = const { RefCell::new(None) };
+}
+
+fn with_machine<R>(f: impl FnOnce(&mut Deep16Machine) -> R) -> R {
+    MACHINE.with(|m| {
+        let mut m = m.borrow_mut();
+        f(m.get_or_insert_with(|| Deep16Machine::new(DEFAULT_MEM_WORDS)))
+    })
 }
 
 #[wasm_bindgen]
 pub fn init(mem_words: usize) {
-    unsafe {
-        let mut c = Cpu::new(mem_words);
-        autoload_rom(&mut c);
-        CPU = Some(c);
-    }
+    MACHINE.with(|m| *m.borrow_mut() = Some(Deep16Machine::new(mem_words)));
 }
 
 #[wasm_bindgen]
 pub fn reset() {
-    unsafe {
-        let c = cpu_mut();
-        c.reset();
-        autoload_rom(c);
-    }
+    with_machine(|m| m.reset())
 }
 
 #[wasm_bindgen]
 pub fn set_segments(cs: u16, ds: u16, ss: u16, es: u16) {
-    unsafe {
-        let c = cpu_mut();
-        c.cs = cs;
-        c.ds = ds;
-        c.ss = ss;
-        c.es = es;
-    }
+    with_machine(|m| m.set_segments(cs, ds, ss, es))
 }
 
 #[wasm_bindgen]
-pub fn load_program(ptr: usize, data: Box<[u16]>) {
-    unsafe {
-        let c = cpu_mut();
-        let len = data.len();
-        if ptr + len > c.mem.len() {
-            return;
-        }
-        for i in 0..len {
-            c.mem[ptr + i] = data[i];
-        }
-        c.reg[15] = 0;
-        c.cs = 0xFFFF;
-    }
+pub fn load_program(ptr: usize, data: Box<[u16]>) -> bool {
+    with_machine(|m| m.load_program(ptr, &data))
 }
 
 #[wasm_bindgen]
 pub fn get_registers() -> Box<[u16]> {
-    unsafe {
-        let c = cpu_ref();
-        let mut v = c.reg.to_vec();
-        if (c.psw & (1 << 5)) != 0 { v[15] = c.spc; }
-        v.into_boxed_slice()
-    }
+    with_machine(|m| m.get_registers())
 }
 
 #[wasm_bindgen]
 pub fn get_psw() -> u16 {
-    unsafe { cpu_ref().psw }
+    with_machine(|m| m.get_psw())
 }
 
 #[wasm_bindgen]
 pub fn get_segments() -> Box<[u16]> {
-    unsafe {
-        let c = cpu_ref();
-        let cs = if (c.psw & (1 << 5)) != 0 { c.scs } else { c.cs };
-        vec![cs, c.ds, c.ss, c.es].into_boxed_slice()
-    }
-}
-
-fn phys(seg: u16, off: u32) -> usize {
-    (((seg as u32) << 4) + off) as usize
+    with_machine(|m| m.get_segments())
 }
 
 #[wasm_bindgen]
 pub fn get_memory_slice(start: usize, count: usize) -> Box<[u16]> {
-    unsafe {
-        let c = cpu_ref();
-        let end = start.saturating_add(count);
-        let end = end.min(c.mem.len());
-        c.mem[start..end].to_vec().into_boxed_slice()
-    }
+    with_machine(|m| m.get_memory_slice(start, count))
 }
 
 #[wasm_bindgen]
 pub fn get_memory_word(addr: usize) -> u16 {
-    unsafe {
-        let c = cpu_ref();
-        if addr < c.mem.len() { c.mem[addr] } else { 0xFFFF }
-    }
-}
-
-fn is_stack_register(psw: u16, idx: usize) -> bool {
-    let sr = ((psw >> 6) & 0xF) as usize;
-    if sr == 0 { return false; }
-    let dual = (psw & (1 << 10)) != 0;
-    if dual { idx == sr || idx == (sr + 1) } else { idx == sr }
-}
-
-fn is_extra_register(psw: u16, idx: usize) -> bool {
-    let er = ((psw >> 11) & 0xF) as usize;
-    if er == 0 { return false; }
-    let dual = (psw & (1 << 15)) != 0;
-    if dual { idx == er || idx == (er + 1) } else { idx == er }
-}
-
-fn update_psw_flags(c: &mut Cpu) {
-    if !c.last_op_alu { return; }
-    let mut psw = c.psw & 0xFFF0;
-    let res16 = (c.last_alu_result as i64) & 0xFFFF;
-    let signed = if (res16 & 0x8000) != 0 { (res16 as i64) - 0x10000 } else { res16 as i64 };
-    if res16 == 0 { psw |= 1 << 1; }
-    if (res16 & 0x8000) != 0 { psw |= 1 << 0; }
-    if c.last_alu_result > 0xFFFF || c.last_alu_result < 0 { psw |= 1 << 3; }
-    if signed > 32767 || signed < -32768 { psw |= 1 << 2; }
-    c.psw = psw;
-    c.last_op_alu = false;
-}
-
-fn exec_ldi(c: &mut Cpu, instr: u16) {
-    let imm = instr & 0x7FFF;
-    c.reg[0] = imm;
-    c.last_alu_result = imm as i32;
-    c.last_op_alu = true;
-}
-
-fn exec_mem(c: &mut Cpu, instr: u16) {
-    let d = (instr >> 13) & 0x1;
-    let rd = ((instr >> 9) & 0xF) as usize;
-    let rb = ((instr >> 5) & 0xF) as usize;
-    let off = (instr & 0x1F) as u32;
-    let addr_off = (c.reg[rb] as u32).wrapping_add(off);
-    let (seg_idx, seg) = if is_stack_register(c.psw, rb) { (2u16, c.ss) } else if is_extra_register(c.psw, rb) { (3u16, c.es) } else { (1u16, c.ds) };
-    let pa = phys(seg, addr_off);
-    if pa >= c.mem.len() { return; }
-    if d == 0 { c.reg[rd] = c.mem[pa]; } else { c.mem[pa] = c.reg[rd]; }
-    c.recent_addr = pa;
-    c.recent_base = c.reg[rb];
-    c.recent_offset = (off & 0x1F) as u16;
-    c.recent_seg_val = seg;
-    c.recent_seg_idx = seg_idx;
-    c.recent_is_store = d == 1;
-}
-
-fn exec_alu(c: &mut Cpu, instr: u16) {
-    let func5 = (instr >> 8) & 0x1F;
-    let rd = ((instr >> 4) & 0xF) as usize;
-    let low4 = (instr & 0xF) as u16;
-    let rdv = c.reg[rd] as u32 & 0xFFFF;
-    let sign = (rdv & 0x8000) != 0;
-    let is_reg = func5 == 0b00000 || func5 == 0b00010 || func5 == 0b00100 || func5 == 0b00110 || func5 == 0b01000 || func5 == 0b01010 || func5 == 0b01100 || func5 == 0b01110 || func5 >= 0b11100;
-    let opv = if is_reg { c.reg[low4 as usize] as u32 & 0xFFFF } else { low4 as u32 & 0xF };
-    let mut result: i32 = rdv as i32;
-    match func5 {
-        0b00000 | 0b00001 => { result = ((rdv + opv) & 0x1FFFF) as i32; }
-        0b00010 | 0b00011 => { result = (rdv as i32 - opv as i32) as i32; }
-        0b00100 | 0b00101 => { result = (rdv as i32 - opv as i32) as i32; c.last_alu_result = result; c.last_op_alu = true; return; }
-        0b00110 | 0b00111 => { result = ((rdv & opv) & 0xFFFF) as i32; }
-        0b01000 => {
-            let masked = (rdv & opv) & 0xFFFF;
-            c.last_alu_result = if masked == 0 { 0 } else { 1 };
-            c.last_op_alu = true;
-            return;
-        }
-        0b01001 => {
-            let bit = ((rdv >> opv) & 1) as i32;
-            c.last_alu_result = if bit == 0 { 1 } else { 0 };
-            c.last_op_alu = true;
-            return;
-        }
-        0b01010 | 0b01011 => { result = ((rdv | opv) & 0xFFFF) as i32; }
-        0b01100 | 0b01101 => { result = ((rdv ^ opv) & 0xFFFF) as i32; }
-        0b01110 => {
-            let masked = (rdv & opv) & 0xFFFF;
-            c.last_alu_result = if masked != 0 { 1 } else { 0 };
-            c.last_op_alu = true;
-            return;
-        }
-        0b01111 => {
-            let bit = ((rdv >> opv) & 1) as i32;
-            c.last_alu_result = if bit == 1 { 1 } else { 0 };
-            c.last_op_alu = true;
-            return;
-        }
-        0b10000 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
-            result = ((rdv << count) & 0xFFFF) as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10001 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
-            let mut val = ((rdv << count) & 0x7FFF) as u16;
-            if sign { val |= 0x8000; }
-            result = val as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10010 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let mut val = ((rdv << count) & 0x7FFF) as u16;
-            if sign { val |= 0x8000; }
-            if count > 0 { val |= (carry_in << (count - 1)) as u16; }
-            result = val as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10011 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (16 - count)) & 1) as u16 } else { 0 };
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let mut val = ((rdv << count) & 0xFFFF) as u16;
-            if count > 0 { val |= (carry_in << (count - 1)) as u16; }
-            result = val as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10100 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
-            result = (rdv >> count) as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10101 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
-            result = ((rdv >> count) | fill) as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10110 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
-            let sign_mask = if sign { 0xFFFFu32 << (16 - count) } else { 0 };
-            result = ((rdv >> count) | (sign_mask & 0xFFFF)) as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b10111 => {
-            let count = (opv & 0xF) as u32;
-            let carry_out = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { 0 };
-            let sign_mask = if sign { 0xFFFFu32 << (16 - count) } else { 0 };
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
-            result = ((rdv >> count) | (sign_mask & 0xFFFF) | fill) as i32;
-            c.psw = (c.psw & !0x8) | ((carry_out as u16) << 3);
-        }
-        0b11000 => {
-            let count = (opv & 0xF) as u32;
-            result = (((rdv << count) | (rdv >> (16 - count))) & 0xFFFF) as i32;
-        }
-        0b11001 => {
-            let count = (opv & 0xF) as u32;
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let fill = if count > 0 { (carry_in as u32) << (count - 1) } else { 0 };
-            result = (((rdv << count) | (rdv >> (16 - count)) | fill) & 0xFFFF) as i32;
-        }
-        0b11010 => {
-            let count = (opv & 0xF) as u32;
-            result = (((rdv >> count) | (rdv << (16 - count))) & 0xFFFF) as i32;
-        }
-        0b11011 => {
-            let count = (opv & 0xF) as u32;
-            let carry_in = ((c.psw >> 3) & 1) as u16;
-            let fill = if count > 0 { (carry_in as u32) << (15 - count) } else { 0 };
-            let new_carry = if count > 0 { ((rdv >> (count - 1)) & 1) as u16 } else { carry_in };
-            result = (((rdv >> count) | (rdv << (16 - count)) | fill) & 0xFFFF) as i32;
-            c.psw = (c.psw & !0x8) | (new_carry << 3);
-        }
-        0b11100 => {
-            let prod = ((rdv as u32 & 0xFFFF) * (opv as u32 & 0xFFFF)) & 0xFFFF;
-            c.reg[rd] = (prod & 0xFFFF) as u16;
-            result = prod as i32;
-        }
-        0b11101 => {
-            let prod = (rdv as u64) * (opv as u64);
-            c.reg[rd] = ((prod >> 16) as u32 & 0xFFFF) as u16;
-            c.reg[rd + 1] = (prod as u32 & 0xFFFF) as u16;
-            result = prod as i32;
-        }
-        0b11110 => {
-            if opv == 0 { result = 0xFFFF; } else {
-                let q = ((rdv as u32) / opv) as u32 & 0xFFFF;
-                let r = ((rdv as u32) % opv) as u32 & 0xFFFF;
-                c.reg[rd] = q as u16;
-                c.reg[rd + 1] = r as u16;
-                result = q as i32;
-            }
-        }
-        0b11111 => {
-            if opv == 0 { result = 0xFFFF; } else {
-                let dividend = (((c.reg[rd] as u32) << 16) | (c.reg[rd + 1] as u32)) as u64;
-                let q = (dividend / opv as u64) as u32 & 0xFFFF;
-                let r = (dividend % opv as u64) as u32 & 0xFFFF;
-                c.reg[rd] = q as u16;
-                c.reg[rd + 1] = r as u16;
-                result = q as i32;
-            }
-        }
-        _ => { }
-    }
-    c.reg[rd] = (result as u32 & 0xFFFF) as u16;
-    c.last_alu_result = result;
-    c.last_op_alu = true;
-}
-
-fn exec_mov(c: &mut Cpu, instr: u16, original_pc: u16) -> bool {
-    let rd = ((instr >> 6) & 0xF) as usize;
-    let rs = ((instr >> 2) & 0xF) as usize;
-    let imm2 = (instr & 0x3) as u16;
-
-    let value: u16 = if imm2 == 0 {
-        c.reg[rs]
-    } else if rs == 15 && imm2 == 2 {
-        // Standard link (LNK): PC architectural read before jump
-        original_pc.wrapping_add(2)
-    } else if rs == 15 && imm2 == 3 {
-        // Architectural link in delay slot (ALNK): next instruction after delay slot
-        original_pc.wrapping_add(1)
-    } else if imm2 == 3 {
-        // Architectural read bypass (AMV)
-        c.reg[rs]
-    } else {
-        c.reg[rs].wrapping_add(imm2)
-    };
-
-    // MOV to PC is a branch with one delay slot
-    if rd == 15 {
-        let in_shadow = (c.psw & (1 << 5)) != 0;
-        c.delay_active = true;
-        c.delayed_pc = value;
-        c.delayed_cs = if in_shadow { c.scs } else { c.cs };
-        c.delayed_to_shadow = in_shadow;
-        c.branch_taken = true;
-        c.last_alu_result = value as i32;
-        c.last_op_alu = true;
-        return true;
-    }
-
-    c.reg[rd] = value;
-    c.last_alu_result = value as i32;
-    c.last_op_alu = true;
-    false
-}
-
-fn exec_lsi(c: &mut Cpu, instr: u16) {
-    let rd = ((instr >> 5) & 0xF) as usize;
-    let mut imm = (instr & 0x1F) as i16;
-    if (imm & 0x10) != 0 { imm |= -1i16 << 5; }
-    c.reg[rd] = imm as u16;
-}
-
-fn exec_sop(c: &mut Cpu, instr: u16) -> bool {
-    let t = (instr >> 4) & 0xF;
-    let rx = (instr & 0xF) as usize;
-    match t {
-        0b0000 => {
-            let v = c.reg[rx];
-            let swapped = (((v & 0x00FF) << 8) | ((v >> 8) & 0x00FF)) as u16;
-            c.reg[rx] = swapped;
-            c.last_alu_result = swapped as i32;
-            c.last_op_alu = true;
-            false
-        }
-        0b0001 => {
-            c.reg[rx] = (!c.reg[rx]) & 0xFFFF;
-            c.last_alu_result = c.reg[rx] as i32;
-            c.last_op_alu = true;
-            false
-        }
-        0b0100 => {
-            if rx % 2 != 0 { return false; }
-            let target_cs = c.reg[rx];
-            let target_pc = c.reg[rx + 1];
-            c.delay_active = true;
-            c.delayed_pc = target_pc;
-            c.delayed_cs = target_cs;
-            c.delayed_to_shadow = (c.psw & (1 << 5)) != 0;
-            c.branch_taken = true;
-            true
-        }
-        0b1000 => {
-            c.psw = (c.psw & !0x03C0) | (((rx as u16) & 0xF) << 6);
-            false
-        }
-        0b1001 => {
-            c.psw = (c.psw & !0x03C0) | (((rx as u16) & 0xF) << 6) | 0x0400;
-            false
-        }
-        0b1010 => {
-            c.psw = (c.psw & !0x7800) | (((rx as u16) & 0xF) << 11);
-            false
-        }
-        0b1011 => {
-            c.psw = (c.psw & !0x7800) | (((rx as u16) & 0xF) << 11) | 0x8000;
-            false
-        }
-        _ => false,
-    }
-}
-
-fn exec_mvs(c: &mut Cpu, instr: u16) {
-    let d = (instr >> 6) & 0x1;
-    let rd = ((instr >> 2) & 0xF) as usize;
-    let seg = (instr & 0x3) as u16;
-    if d == 0 {
-        let v = match seg { 0 => c.cs, 1 => c.ds, 2 => c.ss, _ => c.es };
-        c.reg[rd] = v;
-    } else {
-        let v = c.reg[rd];
-        match seg { 0 => c.cs = v, 1 => c.ds = v, 2 => c.ss = v, _ => c.es = v };
-    }
-}
-
-fn exec_jump(c: &mut Cpu, instr: u16) -> bool {
-    let cond = (instr >> 9) & 0x7;
-    let mut off = instr & 0x1FF;
-    if (off & 0x100) != 0 { off = (off as i32 - 0x200) as u16; }
-    let z = (c.psw & (1 << 1)) != 0;
-    let cflag = (c.psw & (1 << 3)) != 0;
-    let nflag = (c.psw & (1 << 0)) != 0;
-    let oflag = (c.psw & (1 << 2)) != 0;
-    let mut j = false;
-    match cond {
-        0 => j = z,
-        1 => j = !z,
-        2 => j = cflag,
-        3 => j = !cflag,
-        4 => j = nflag,
-        5 => j = !nflag,
-        6 => j = oflag,
-        _ => j = !oflag,
-    }
-    if j {
-        let in_shadow = (c.psw & (1 << 5)) != 0;
-        let current_pc = if in_shadow { c.spc } else { c.reg[15] } as i32;
-        let target_pc = (current_pc + (off as i16 as i32)) as u16;
-        c.delay_active = true;
-        c.delayed_pc = target_pc;
-        c.delayed_cs = if in_shadow { c.scs } else { c.cs };
-        c.delayed_to_shadow = in_shadow;
-        c.branch_taken = true;
-    } else {
-        c.branch_taken = false;
-    }
-    true
-}
-
-fn step_one(c: &mut Cpu) -> bool {
-    if !c.running { c.running = true; }
-    let in_shadow = (c.psw & (1 << 5)) != 0;
-    if c.delay_active {
-        c.delay_active = false;
-        let active_cs = if in_shadow { c.scs } else { c.cs };
-        let active_pc = if in_shadow { c.spc } else { c.reg[15] };
-        let pa = phys(active_cs, active_pc as u32);
-        if pa >= c.mem.len() { c.running = false; return false; }
-        let instr = c.mem[pa];
-        let original_pc = active_pc;
-        if in_shadow { c.spc = c.spc.wrapping_add(1); } else { c.reg[15] = c.reg[15].wrapping_add(1); }
-        c.last_op_alu = false;
-        c.last_alu_result = 0;
-        let is_branch = exec_instruction(c, instr, original_pc);
-        update_psw_flags(c);
-        if c.branch_taken {
-            if c.delayed_to_shadow { c.spc = c.delayed_pc; c.scs = c.delayed_cs; } else { c.reg[15] = c.delayed_pc; c.cs = c.delayed_cs; }
-        }
-        return !is_branch || c.running;
-    }
-    let active_cs = if in_shadow { c.scs } else { c.cs };
-    let active_pc = if in_shadow { c.spc } else { c.reg[15] };
-    let pa = phys(active_cs, active_pc as u32);
-    if pa >= c.mem.len() { c.running = false; return false; }
-    let instr = c.mem[pa];
-    if instr == 0xFFFF { c.running = false; return false; }
-    c.last_event_code = instr;
-    c.last_event_spc = active_pc;
-    c.last_event_scs = active_cs;
-    if (instr & 0xFFF0) == 0xFFF0 {
-        exec_sys(c, instr);
-        update_psw_flags(c);
-        return true;
-    }
-    let original_pc = active_pc;
-    if in_shadow { c.spc = c.spc.wrapping_add(1); } else { c.reg[15] = c.reg[15].wrapping_add(1); }
-    c.last_op_alu = false;
-    c.last_alu_result = 0;
-    let _is_branch = exec_instruction(c, instr, original_pc);
-    update_psw_flags(c);
-    true
-}
-
-fn exec_instruction(c: &mut Cpu, instr: u16, original_pc: u16) -> bool {
-    // Fast-path: System instructions (NOP/HLT/SWI/RETI)
-    if (instr & 0xFFF0) == 0xFFF0 { exec_sys(c, instr); return false; }
-    if (instr & 0x8000) == 0 { exec_ldi(c, instr); return false; }
-    if ((instr >> 14) & 0x3) == 0b10 { exec_mem(c, instr); return false; }
-    let opcode3 = (instr >> 13) & 0x7;
-    match opcode3 {
-        0b110 => { exec_alu(c, instr); false }
-        0b111 => {
-            if ((instr >> 12) & 0xF) == 0b1110 { return exec_jump(c, instr); }
-            if ((instr >> 11) & 0x1F) == 0b11110 { exec_lds_sts(c, instr); return false; }
-            if ((instr >> 10) & 0x3F) == 0b111110 { return exec_mov(c, instr, original_pc); }
-            // System instruction (SWI/RETI/etc.) must be detected before other 0b111* groups
-            if (instr & 0xFFF0) == 0xFFF0 { exec_sys(c, instr); return false; }
-            if ((instr >> 3) & 0x1FFF) == 0x1FFE { exec_sys(c, instr); return false; }
-            if ((instr >> 9) & 0x7F) == 0b1111110 { exec_lsi(c, instr); return false; }
-            if ((instr >> 8) & 0xFF) == 0b11111110 { return exec_sop(c, instr); }
-            if ((instr >> 7) & 0x1FF) == 0b111111110 { exec_mvs(c, instr); return false; }
-            false
-        }
-        _ => false,
-    }
-}
-
-fn autoload_rom(c: &mut Cpu) {
-    let base = 0xFFFF0usize;
-    let rom: [u16; 16] = [
-        0x0000, // LDI 0 -> R0
-        0xFF41, // MVS DS, R0
-        0xFF42, // MVS SS, R0
-        0xFC21, // LSI R1, 1
-        0xFE01, // SWB R1
-        0xA200, // ST R1, [R0+0]
-        0xA201, // ST R1, [R0+1]
-        0xA202, // ST R1, [R0+2]
-        0xFE40, // JML R0
-        0xFFF0, // NOP (delay slot)
-        0xFFF1, // HLT
-        0xFFF1, // HLT
-        0xFFF1, // HLT
-        0xFFF1, // HLT
-        0xFFF1, // HLT
-        0xFFF1, // HLT
-    ];
-    for i in 0..rom.len() {
-        let addr = base + i;
-        if addr < c.mem.len() { c.mem[addr] = rom[i]; }
-    }
-}
-
-fn exec_lds_sts(c: &mut Cpu, instr: u16) {
-    // [11110][d1][seg2][Rd4][Rs4]
-    let d = (instr >> 10) & 0x1;
-    let seg = (instr >> 8) & 0x3;
-    let rd = ((instr >> 4) & 0xF) as usize;
-    let rs = (instr & 0xF) as usize;
-    let base = c.reg[rs] as u32;
-    let segv = match seg { 0 => c.cs, 1 => c.ds, 2 => c.ss, _ => c.es };
-    let pa = phys(segv, base);
-    if pa >= c.mem.len() { return; }
-    if d == 0 { c.reg[rd] = c.mem[pa]; } else { c.mem[pa] = c.reg[rd]; }
-    c.recent_addr = pa;
-    c.recent_base = c.reg[rs];
-    c.recent_offset = 0;
-    c.recent_seg_val = segv;
-    c.recent_seg_idx = seg as u16;
-    c.recent_is_store = d == 1;
+    with_machine(|m| m.get_memory_word(addr))
 }
 
 #[wasm_bindgen]
 pub fn step() -> bool {
-    unsafe {
-        let c = cpu_mut();
-        step_one(c)
-    }
+    with_machine(|m| m.step())
 }
 
 #[wasm_bindgen]
 pub fn run_steps(n: u32) -> bool {
-    unsafe {
-        let c = cpu_mut();
-        let mut cont = true;
-        for _ in 0..n {
-            if !step_one(c) { cont = false; break; }
-        }
-        cont
-    }
+    with_machine(|m| m.run_steps(n))
+}
+
+#[wasm_bindgen]
+pub fn save_state() -> Vec<u8> {
+    with_machine(|m| m.save_state())
+}
+
+#[wasm_bindgen]
+pub fn load_state(data: &[u8]) -> Result<(), JsError> {
+    with_machine(|m| m.load_state(data))
 }
+
+#[wasm_bindgen]
+pub fn set_trace(capacity: usize) {
+    with_machine(|m| m.set_trace(capacity))
+}
+
+#[wasm_bindgen]
+pub fn set_trace_filter(start: usize, end: usize, classes: u32) {
+    with_machine(|m| m.set_trace_filter(start, end, classes))
+}
+
+#[wasm_bindgen]
+pub fn get_trace_len() -> usize {
+    with_machine(|m| m.get_trace_len())
+}
+
+#[wasm_bindgen]
+pub fn take_trace_binary() -> Vec<u8> {
+    with_machine(|m| m.take_trace_binary())
+}
+
+#[wasm_bindgen]
+pub fn take_trace_text() -> String {
+    with_machine(|m| m.take_trace_text())
+}
+
+/// Disassembles `instr` fetched from offset `pc` (jump targets are shown).
+#[wasm_bindgen]
+pub fn disassemble(instr: u16, pc: u16) -> String {
+    disassemble_at(instr, pc)
+}
+
+#[wasm_bindgen]
+pub fn step_back(n: u32) -> u32 {
+    with_machine(|m| m.step_back(n))
+}
+
+#[wasm_bindgen]
+pub fn set_history_limit(limit: usize) {
+    with_machine(|m| m.set_history_limit(limit))
+}
+
+#[wasm_bindgen]
+pub fn get_history_len() -> usize {
+    with_machine(|m| m.get_history_len())
+}
+
+#[wasm_bindgen]
+pub fn get_stop_reason() -> Box<[u32]> {
+    with_machine(|m| m.get_stop_reason())
+}
+
+#[wasm_bindgen]
+pub fn get_stop_message() -> String {
+    with_machine(|m| m.get_stop_message())
+}
+
+#[wasm_bindgen]
+pub fn set_illegal_policy(mode: u8) {
+    with_machine(|m| m.set_illegal_policy(mode))
+}
+
+#[wasm_bindgen]
+pub fn set_timing_mode(enabled: bool) {
+    with_machine(|m| m.set_timing_mode(enabled))
+}
+
+#[wasm_bindgen]
+pub fn get_cycle_counters() -> Box<[f64]> {
+    with_machine(|m| m.get_cycle_counters())
+}
+
+#[wasm_bindgen]
+pub fn reset_cycle_counters() {
+    with_machine(|m| m.reset_cycle_counters())
+}
+
+#[wasm_bindgen]
+pub fn step_cycle() -> bool {
+    with_machine(|m| m.step_cycle())
+}
+
+#[wasm_bindgen]
+pub fn get_pipeline_stages() -> Box<[u32]> {
+    with_machine(|m| m.get_pipeline_stages())
+}
+
+#[wasm_bindgen]
+pub fn add_breakpoint(addr: usize) -> u32 {
+    with_machine(|m| m.add_breakpoint(addr))
+}
+
+#[wasm_bindgen]
+pub fn add_breakpoint_cs_pc(cs: u16, pc: u16) -> u32 {
+    with_machine(|m| m.add_breakpoint_cs_pc(cs, pc))
+}
+
+#[wasm_bindgen]
+pub fn set_breakpoint_condition(id: u32, condition: &str) -> Result<(), JsError> {
+    with_machine(|m| m.set_breakpoint_condition(id, condition))
+}
+
+#[wasm_bindgen]
+pub fn set_breakpoint_ignore_count(id: u32, ignore: u32) -> bool {
+    with_machine(|m| m.set_breakpoint_ignore_count(id, ignore))
+}
+
+#[wasm_bindgen]
+pub fn get_breakpoint_hits(id: u32) -> Option<u32> {
+    with_machine(|m| m.get_breakpoint_hits(id))
+}
+
+#[wasm_bindgen]
+pub fn remove_breakpoint(id: u32) -> bool {
+    with_machine(|m| m.remove_breakpoint(id))
+}
+
+#[wasm_bindgen]
+pub fn clear_breakpoints() {
+    with_machine(|m| m.clear_breakpoints())
+}
+
+#[wasm_bindgen]
+pub fn add_watchpoint(start: usize, end: usize, kind: u8) -> u32 {
+    with_machine(|m| m.add_watchpoint(start, end, kind))
+}
+
+#[wasm_bindgen]
+pub fn remove_watchpoint(id: u32) -> bool {
+    with_machine(|m| m.remove_watchpoint(id))
+}
+
+#[wasm_bindgen]
+pub fn clear_watchpoints() {
+    with_machine(|m| m.clear_watchpoints())
+}
+
+#[wasm_bindgen]
+pub fn get_watch_hit() -> Box<[u32]> {
+    with_machine(|m| m.get_watch_hit())
+}
+
+#[wasm_bindgen]
+pub fn raise_interrupt() {
+    with_machine(|m| m.raise_interrupt())
+}
+
+#[wasm_bindgen]
+pub fn set_irq_line(level: bool) {
+    with_machine(|m| m.set_irq_line(level))
+}
+
+#[wasm_bindgen]
+pub fn raise_nmi() {
+    with_machine(|m| m.raise_nmi())
+}
+
+/// Queues a key code in the keyboard controller at 0xF0060; `false` if its
+/// FIFO is full.
+#[wasm_bindgen]
+pub fn push_key(code: u16) -> bool {
+    with_machine(|m| m.push_key(code))
+}
+
+/// Queues bytes for the program to receive on the serial port at 0xF0040.
+#[wasm_bindgen]
+pub fn uart_write_input(bytes: &[u8]) {
+    with_machine(|m| m.uart_write_input(bytes))
+}
+
+/// Bytes the program has sent on the serial port since the last call.
+#[wasm_bindgen]
+pub fn uart_read_output() -> Vec<u8> {
+    with_machine(|m| m.uart_read_output())
+}
+
+/// The 80x25 screen as text, one line per row without trailing blanks.
+#[wasm_bindgen]
+pub fn get_screen_text() -> String {
+    with_machine(|m| m.get_screen_text())
+}
+
+/// `count` screen cells from cell `start` (row * 80 + column), attributes in
+/// the high byte.
+#[wasm_bindgen]
+pub fn get_screen_cells(start: usize, count: usize) -> Box<[u16]> {
+    with_machine(|m| m.get_screen_cells(start, count))
+}
+
+/// Screen cells changed since the last call, as `[row, start, end, ...]`
+/// triples covering columns `start..end` of each changed row.
+#[wasm_bindgen]
+pub fn get_screen_dirty() -> Box<[u16]> {
+    with_machine(|m| m.get_screen_dirty())
+}
+
+/// Video controller control, cursor column, cursor row and default
+/// attributes.
+#[wasm_bindgen]
+pub fn get_video_registers() -> Box<[u16]> {
+    with_machine(|m| m.get_video_registers())
+}
+
 #[wasm_bindgen]
 pub fn get_recent_access() -> Box<[u32]> {
-    unsafe {
-        let c = cpu_ref();
-        vec![
-            c.recent_addr as u32,
-            c.recent_base as u32,
-            c.recent_offset as u32,
-            c.recent_seg_val as u32,
-            c.recent_seg_idx as u32,
-            if c.recent_is_store { 1 } else { 0 },
-        ].into_boxed_slice()
-    }
+    with_machine(|m| m.get_recent_access())
 }
 
 #[wasm_bindgen]
 pub fn get_last_event() -> Box<[u16]> {
-    unsafe {
-        let c = cpu_ref();
-        vec![c.last_event_code, c.spc, c.scs, c.psw, c.spsw].into_boxed_slice()
-    }
+    with_machine(|m| m.get_last_event())
 }
 
 #[wasm_bindgen]
 pub fn get_shadow_state() -> Box<[u16]> {
-    unsafe {
-        let c = cpu_ref();
-        vec![c.spc, c.scs, c.spsw].into_boxed_slice()
-    }
-}
-fn exec_sys(c: &mut Cpu, instr: u16) -> bool {
-    let op = (instr & 0x7) as u16;
-    match op {
-        0 => { /* NOP */ false }
-        1 => { /* HLT */ c.running = false; false }
-        2 => { /* SWI */
-            c.spsw = c.psw;
-            c.psw = (c.psw & !(1 << 4)) | (1 << 5);
-            c.scs = 0x0000;
-            let pa = phys(0, 2u32);
-            let target = if pa < c.mem.len() { c.mem[pa] & 0xFFFF } else { 0xFFFF };
-            c.spc = target;
-            c.last_event_code = 2;
-            c.last_event_spc = c.spc;
-            c.last_event_scs = c.scs;
-            false
-        }
-        3 => { /* RETI */
-            // Return from interrupt: clear S-bit
-            c.psw = c.psw & !(1 << 5);
-            c.last_event_code = 3;
-            false
-        }
-        _ => false,
-    }
+    with_machine(|m| m.get_shadow_state())
+}
+
+#[wasm_bindgen]
+pub fn get_normal_state() -> Box<[u16]> {
+    with_machine(|m| m.get_normal_state())
 }